@@ -1,54 +1,327 @@
-use std::sync::Arc;
+use std::{fmt, sync::Arc};
 
-pub use anthropic::Model;
-use anthropic::{
-    messages::{ContentPart, CreateMessageRequest, Event, MessagesStream, ToolInputSchema},
-    Anthropic, AnthropicBuilder,
-};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use ferrochain::{
     anyhow::{anyhow, Result},
-    completion::{Completion, CompletionResponse, StreamEvent, StreamEventEnvelope},
-    futures::{lock::Mutex, StreamExt},
-    message::{Content, ImageSource, Message, ToolUse},
+    batch_completion::{
+        BatchCompletion, BatchOutcome, BatchRequest, BatchResult, BatchResults, BatchState,
+        BatchStatus,
+    },
+    completion::{
+        Completion, CompletionOptions, CompletionResponse, StreamEvent, StreamEventEnvelope,
+        ToolChoice, ToolSelection, Usage,
+    },
+    config::{ComponentFactory, Components},
+    futures::{
+        future, io::BufReader, lock::Mutex, AsyncBufReadExt, AsyncReadExt, Stream, StreamExt,
+        TryStreamExt,
+    },
+    message::{Content, DocumentSource, ImageSource, Message, Role, ToolResult, ToolUse},
     tool::{ToolDescriptor, ToolProvider},
 };
-use http_client::HttpClient;
+use http_client::{AsyncBody, HttpClient, Method, Request};
+use serde_json::{json, Value};
+
+/// The smallest thinking budget accepted by the API.
+const MIN_THINKING_BUDGET: u32 = 1024;
+
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+const API_VERSION: &str = "2023-06-01";
+
+/// The image media types accepted by the API.
+const IMAGE_MEDIA_TYPES: [&str; 4] = ["image/jpeg", "image/png", "image/gif", "image/webp"];
+
+/// A Claude model, serialized as its API identifier.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+pub enum Model {
+    #[serde(rename = "claude-3-haiku-20240307")]
+    Claude3Haiku20240307,
+    #[serde(rename = "claude-3-opus-20240229")]
+    Claude3Opus20240229,
+    #[serde(rename = "claude-3-5-haiku-20241022")]
+    Claude35Haiku20241022,
+    #[serde(rename = "claude-3-5-sonnet-20240620")]
+    Claude35Sonnet20240620,
+    #[serde(rename = "claude-3-5-sonnet-20241022")]
+    Claude35Sonnet20241022,
+    #[serde(rename = "claude-3-7-sonnet-20250219")]
+    Claude37Sonnet20250219,
+    #[serde(rename = "claude-sonnet-4-20250514")]
+    ClaudeSonnet420250514,
+    #[serde(rename = "claude-opus-4-20250514")]
+    ClaudeOpus420250514,
+    /// Any other model, by its identifier.
+    #[serde(untagged)]
+    Other(String),
+}
+
+impl Model {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Model::Claude3Haiku20240307 => "claude-3-haiku-20240307",
+            Model::Claude3Opus20240229 => "claude-3-opus-20240229",
+            Model::Claude35Haiku20241022 => "claude-3-5-haiku-20241022",
+            Model::Claude35Sonnet20240620 => "claude-3-5-sonnet-20240620",
+            Model::Claude35Sonnet20241022 => "claude-3-5-sonnet-20241022",
+            Model::Claude37Sonnet20250219 => "claude-3-7-sonnet-20250219",
+            Model::ClaudeSonnet420250514 => "claude-sonnet-4-20250514",
+            Model::ClaudeOpus420250514 => "claude-opus-4-20250514",
+            Model::Other(model) => model,
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 pub struct AnthropicCompletion {
-    sdk: Anthropic,
+    http_client: Arc<dyn HttpClient>,
+    api_key: String,
+    base_url: String,
     model: Model,
     system: Option<Vec<Content>>,
     temperature: Option<f32>,
     max_tokens: usize,
     tool_provider: Option<ToolProvider>,
+    tool_choice: ToolChoice,
+    cache_system: bool,
+    cache_tools: bool,
+    thinking_budget: Option<u32>,
+    stop_sequences: Option<Vec<String>>,
+    top_k: Option<u32>,
+    top_p: Option<f32>,
+    user_id: Option<String>,
 }
 
 #[derive(Clone)]
 pub struct AnthropicCompletionBuilder {
-    builder: AnthropicBuilder,
+    http_client: Option<Arc<dyn HttpClient>>,
+    api_key: Option<String>,
+    base_url: Option<String>,
     model: Option<Model>,
     system: Option<Vec<Content>>,
     temperature: Option<f32>,
     max_tokens: Option<usize>,
     tool_provider: Option<ToolProvider>,
+    tool_choice: Option<ToolChoice>,
+    cache_system: bool,
+    cache_tools: bool,
+    thinking_budget: Option<u32>,
+    stop_sequences: Option<Vec<String>>,
+    top_k: Option<u32>,
+    top_p: Option<f32>,
+    user_id: Option<String>,
 }
 
 impl AnthropicCompletion {
     pub fn builder() -> AnthropicCompletionBuilder {
         AnthropicCompletionBuilder {
-            builder: Anthropic::builder(),
+            http_client: None,
+            api_key: None,
+            base_url: None,
             model: None,
             system: None,
             temperature: None,
             max_tokens: None,
             tool_provider: None,
+            tool_choice: None,
+            cache_system: false,
+            cache_tools: false,
+            thinking_budget: None,
+            stop_sequences: None,
+            top_k: None,
+            top_p: None,
+            user_id: None,
+        }
+    }
+
+    /// Replaces the images given by URL, including those of tool results, with their base64
+    /// data, since the API only accepts inline images.
+    async fn inline_url_images(&self, mut messages: Vec<Message>) -> Result<Vec<Message>> {
+        let contents = messages
+            .iter_mut()
+            .flat_map(|message| &mut message.content)
+            .flat_map(|content| match content {
+                Content::ToolResult(tool_result) => tool_result.content.iter_mut().collect(),
+                content => vec![content],
+            });
+        for content in contents {
+            let Content::Image {
+                source: ImageSource::Url { url, media_type },
+            } = content
+            else {
+                continue;
+            };
+
+            let (data, fetched_media_type) = fetch_image(self.http_client.as_ref(), url).await?;
+            *content = Content::Image {
+                source: ImageSource::Base64 {
+                    data: BASE64.encode(data),
+                    media_type: media_type.take().or(fetched_media_type),
+                },
+            };
+        }
+
+        Ok(messages)
+    }
+
+    /// Builds the request completing `messages`, the settings of `options` overriding those
+    /// of the builder.
+    async fn create_message_request(
+        &self,
+        messages: Vec<Message>,
+        options: CompletionOptions,
+    ) -> Result<CreateMessageRequest> {
+        let tools = options.tools.unwrap_or_else(|| {
+            ToolSelection::default()
+                .with_choice(self.tool_choice.clone())
+                .with_cache_breakpoint(self.cache_tools)
+        });
+        let max_tokens = options.max_tokens.unwrap_or(self.max_tokens);
+        let temperature = options.temperature.or(self.temperature);
+
+        if let Some(budget_tokens) = self.thinking_budget {
+            check_thinking_budget(budget_tokens, max_tokens, temperature)?;
+        }
+        if self.thinking_budget.is_some()
+            && matches!(tools.choice, ToolChoice::Any | ToolChoice::Tool { .. })
+        {
+            return Err(anyhow!(
+                "the model can't be forced to use a tool with extended thinking"
+            ));
         }
+
+        // With `ToolChoice::None` the tools are still sent, since a conversation which used
+        // them is rejected without their definitions.
+        let (tools, tool_choice) = match &self.tool_provider {
+            Some(tool_provider) => {
+                let mut descriptors = tool_provider
+                    .select(&tools)?
+                    .into_iter()
+                    .map(ferrochain_tool_descriptor_to_anthropic)
+                    .collect::<Result<Vec<_>>>()?;
+                // A breakpoint on the last tool caches every tool definition.
+                if let (true, Some(last)) = (tools.cache_breakpoint, descriptors.last_mut()) {
+                    last.cache_control = Some(CacheControl::Ephemeral);
+                }
+                (
+                    Some(descriptors),
+                    ferrochain_tool_choice_to_anthropic(tools.choice),
+                )
+            }
+            None => (None, None),
+        };
+
+        let system = options
+            .system
+            .or_else(|| self.system.clone())
+            .map(|system| ferrochain_contents_to_anthropic(system, self.cache_system))
+            .transpose()?;
+        let messages = self
+            .inline_url_images(messages)
+            .await?
+            .into_iter()
+            .map(|m| {
+                Ok(AnthropicMessage {
+                    role: m.role,
+                    content: ferrochain_contents_to_anthropic(m.content, m.cache_breakpoint)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let (system, messages) = lift_system_messages(system, messages);
+
+        Ok(CreateMessageRequest {
+            model: self.model.to_string(),
+            messages,
+            max_tokens: u32::try_from(max_tokens)
+                .map_err(|_| anyhow!("max_tokens must be at most {}", u32::MAX))?,
+            metadata: Metadata {
+                user_id: options.user_id.or_else(|| self.user_id.clone()),
+            },
+            stop_sequences: options
+                .stop_sequences
+                .or_else(|| self.stop_sequences.clone()),
+            system,
+            temperature,
+            tools,
+            tool_choice,
+            top_k: options.top_k.or(self.top_k),
+            top_p: options.top_p.or(self.top_p),
+            thinking: self
+                .thinking_budget
+                .map(|budget_tokens| ThinkingConfig::Enabled { budget_tokens }),
+            stream: false,
+        })
+    }
+
+    /// Sends a request to the API, returning the body of the response.
+    async fn send_request(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<Value>,
+    ) -> Result<AsyncBody> {
+        let request = Request::builder()
+            .method(method)
+            .uri(url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+            .header("content-type", "application/json")
+            .body(match body {
+                Some(body) => AsyncBody::from(serde_json::to_vec(&body)?),
+                None => AsyncBody::empty(),
+            })?;
+        let mut response = self.http_client.send(request).await?;
+        if !response.status().is_success() {
+            let mut error = String::new();
+            response.body_mut().read_to_string(&mut error).await?;
+            return Err(anyhow!(
+                "the request to {} failed with {}: {}",
+                url,
+                response.status(),
+                error
+            ));
+        }
+
+        Ok(response.into_body())
+    }
+}
+
+/// Downloads an image, along with the media type announced by the server.
+async fn fetch_image(http_client: &dyn HttpClient, url: &str) -> Result<(Vec<u8>, Option<String>)> {
+    let mut response = http_client
+        .send(Request::get(url).body(AsyncBody::empty())?)
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "failed to fetch the image at {}: {}",
+            url,
+            response.status()
+        ));
     }
+
+    let media_type = response
+        .headers()
+        .get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(';').next().unwrap_or(value).trim().to_string())
+        .filter(|value| value.starts_with("image/"));
+    let mut data = vec![];
+    response.body_mut().read_to_end(&mut data).await?;
+
+    Ok((data, media_type))
 }
 
 impl AnthropicCompletionBuilder {
+    /// Sets the HTTP client used to reach the API, and to fetch the images given by URL.
+    ///
+    /// The client is required: there is no default one, so [`build`](Self::build) fails
+    /// without it.
     pub fn with_http_client(mut self, client: Arc<dyn HttpClient>) -> Self {
-        self.builder.with_http_client(client);
+        self.http_client = Some(client);
         self
     }
 
@@ -56,7 +329,7 @@ impl AnthropicCompletionBuilder {
     where
         S: AsRef<str>,
     {
-        self.builder.with_api_key(api_key);
+        self.api_key = Some(api_key.as_ref().to_string());
         self
     }
 
@@ -64,7 +337,7 @@ impl AnthropicCompletionBuilder {
     where
         S: AsRef<str>,
     {
-        self.builder.with_base_url(base_url);
+        self.base_url = Some(base_url.as_ref().trim_end_matches('/').to_string());
         self
     }
 
@@ -93,163 +366,322 @@ impl AnthropicCompletionBuilder {
         self
     }
 
+    /// Sets the tool choice used by [`Completion::complete`], defaulting to
+    /// [`ToolChoice::Auto`].
+    pub fn with_tool_choice(mut self, tool_choice: ToolChoice) -> Self {
+        self.tool_choice = Some(tool_choice);
+        self
+    }
+
+    /// Marks a cache breakpoint after the system prompt, so that it is read from the prompt
+    /// cache on later requests.
+    pub fn with_cache_system(mut self, cache_system: bool) -> Self {
+        self.cache_system = cache_system;
+        self
+    }
+
+    /// Marks a cache breakpoint after the tool definitions sent by [`Completion::complete`],
+    /// so that they are read from the prompt cache on later requests.
+    pub fn with_cache_tools(mut self, cache_tools: bool) -> Self {
+        self.cache_tools = cache_tools;
+        self
+    }
+
+    /// Enables extended thinking, letting the model spend up to `budget_tokens` of
+    /// `max_tokens` reasoning before answering.
+    ///
+    /// The budget must be at least 1024 tokens, and the temperature can't be set along with
+    /// it.
+    pub fn with_thinking_budget(mut self, budget_tokens: u32) -> Self {
+        self.thinking_budget = Some(budget_tokens);
+        self
+    }
+
+    pub fn with_stop_sequences<I, S>(mut self, stop_sequences: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.stop_sequences = Some(stop_sequences.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn with_top_k(mut self, top_k: u32) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    /// Sets the id of the end user sent with every request, which Anthropic uses to detect
+    /// abuse.
+    pub fn with_user_id<S>(mut self, user_id: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
     pub fn build(self) -> Result<AnthropicCompletion> {
+        let max_tokens = self
+            .max_tokens
+            .ok_or_else(|| anyhow!("max_tokens is required"))?;
+        if let Some(budget_tokens) = self.thinking_budget {
+            if budget_tokens < MIN_THINKING_BUDGET {
+                return Err(anyhow!(
+                    "the thinking budget must be at least {} tokens",
+                    MIN_THINKING_BUDGET
+                ));
+            }
+            check_thinking_budget(budget_tokens, max_tokens, self.temperature)?;
+        }
+
         Ok(AnthropicCompletion {
-            sdk: self.builder.build()?,
             model: self.model.ok_or_else(|| anyhow!("model is required"))?,
+            http_client: self
+                .http_client
+                .ok_or_else(|| anyhow!("http_client is required"))?,
+            api_key: self.api_key.ok_or_else(|| anyhow!("api_key is required"))?,
+            base_url: self
+                .base_url
+                .unwrap_or_else(|| DEFAULT_BASE_URL.to_string()),
             system: self.system,
             temperature: self.temperature,
-            max_tokens: self
-                .max_tokens
-                .ok_or_else(|| anyhow!("max_tokens is required"))?,
+            max_tokens,
             tool_provider: self.tool_provider,
+            tool_choice: self.tool_choice.unwrap_or_default(),
+            cache_system: self.cache_system,
+            cache_tools: self.cache_tools,
+            thinking_budget: self.thinking_budget,
+            stop_sequences: self.stop_sequences,
+            top_k: self.top_k,
+            top_p: self.top_p,
+            user_id: self.user_id,
         })
     }
 }
 
+/// Checks that the settings of a request leave room for an answer after thinking.
+fn check_thinking_budget(
+    budget_tokens: u32,
+    max_tokens: usize,
+    temperature: Option<f32>,
+) -> Result<()> {
+    if budget_tokens as usize >= max_tokens {
+        return Err(anyhow!("the thinking budget must be less than max_tokens"));
+    }
+    if temperature.is_some() {
+        return Err(anyhow!(
+            "the temperature can't be set along with extended thinking"
+        ));
+    }
+    Ok(())
+}
+
+/// Builds [`AnthropicCompletion`]s out of their configuration, see [`ferrochain::config`].
+#[derive(Clone, Default)]
+pub struct AnthropicCompletionFactory {
+    http_client: Option<Arc<dyn HttpClient>>,
+    tool_provider: Option<ToolProvider>,
+}
+
+impl AnthropicCompletionFactory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_http_client(mut self, http_client: Arc<dyn HttpClient>) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    pub fn with_tool_provider(mut self, tool_provider: ToolProvider) -> Self {
+        self.tool_provider = Some(tool_provider);
+        self
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnthropicCompletionParams {
+    api_key: String,
+    base_url: Option<String>,
+    model: Model,
+    system: Option<String>,
+    temperature: Option<f32>,
+    max_tokens: usize,
+    /// Whether to cache the system prompt and the tool definitions.
+    #[serde(default)]
+    cache: bool,
+    thinking_budget: Option<u32>,
+    stop_sequences: Option<Vec<String>>,
+    top_k: Option<u32>,
+    top_p: Option<f32>,
+}
+
+#[ferrochain::async_trait]
+impl ComponentFactory<dyn Completion> for AnthropicCompletionFactory {
+    async fn build(&self, params: Value, _: &Components) -> Result<Box<dyn Completion>> {
+        let params: AnthropicCompletionParams = serde_json::from_value(params)?;
+
+        let mut builder = AnthropicCompletion::builder()
+            .with_api_key(params.api_key)
+            .with_model(params.model)
+            .with_max_tokens(params.max_tokens)
+            .with_cache_system(params.cache)
+            .with_cache_tools(params.cache);
+        if let Some(http_client) = self.http_client.clone() {
+            builder = builder.with_http_client(http_client);
+        }
+        if let Some(base_url) = params.base_url {
+            builder = builder.with_base_url(base_url);
+        }
+        if let Some(system) = params.system {
+            builder = builder.with_system(vec![system.into()]);
+        }
+        if let Some(temperature) = params.temperature {
+            builder = builder.with_temperature(temperature);
+        }
+        if let Some(thinking_budget) = params.thinking_budget {
+            builder = builder.with_thinking_budget(thinking_budget);
+        }
+        if let Some(stop_sequences) = params.stop_sequences {
+            builder = builder.with_stop_sequences(stop_sequences);
+        }
+        if let Some(top_k) = params.top_k {
+            builder = builder.with_top_k(top_k);
+        }
+        if let Some(top_p) = params.top_p {
+            builder = builder.with_top_p(top_p);
+        }
+        if let Some(tool_provider) = self.tool_provider.clone() {
+            builder = builder.with_tool_provider(tool_provider);
+        }
+
+        Ok(Box::new(builder.build()?))
+    }
+}
+
 #[ferrochain::async_trait]
 impl Completion for AnthropicCompletion {
     async fn complete(&self, messages: Vec<Message>) -> Result<CompletionResponse> {
-        let messages: Vec<anthropic::messages::Message> = messages
-            .into_iter()
-            .map(|m| anthropic::messages::Message {
-                role: match m.role.as_str() {
-                    "user" => anthropic::messages::Role::User,
-                    "assistant" => anthropic::messages::Role::Assistant,
-                    _ => anthropic::messages::Role::User, // Default to User for unknown roles
-                },
-                content: anthropic::messages::Content::Multi(
-                    m.content
-                        .into_iter()
-                        .map(ferrochain_content_to_anthropic)
-                        .collect(),
-                ),
-            })
-            .collect();
-
-        let mut s = self
-            .sdk
-            .messages_stream(CreateMessageRequest {
-                model: self.model.to_string(),
-                messages,
-                max_tokens: self.max_tokens as u32,
-                metadata: Default::default(),
-                stop_sequences: None,
-                system: self.system.to_owned().map(|parts| {
-                    anthropic::messages::Content::Multi(
-                        parts
-                            .into_iter()
-                            .map(ferrochain_content_to_anthropic)
-                            .collect(),
-                    )
-                }),
-                temperature: self.temperature,
-                tools: self.tool_provider.clone().map(|tool_provider| {
-                    tool_provider
-                        .list()
-                        .map(ferrochain_tool_descriptor_to_anthropic)
-                        .collect()
-                }),
-                tool_choice: None,
-                top_k: None,
-                top_p: None,
-            })
+        self.complete_with_options(messages, CompletionOptions::default())
+            .await
+    }
+
+    async fn complete_with_tools(
+        &self,
+        messages: Vec<Message>,
+        tools: ToolSelection,
+    ) -> Result<CompletionResponse> {
+        self.complete_with_options(messages, CompletionOptions::default().with_tools(tools))
+            .await
+    }
+
+    /// Completes `messages`, the settings of `options` overriding those of the builder.
+    async fn complete_with_options(
+        &self,
+        messages: Vec<Message>,
+        options: CompletionOptions,
+    ) -> Result<CompletionResponse> {
+        let request = CreateMessageRequest {
+            stream: true,
+            ..self.create_message_request(messages, options).await?
+        };
+        let body = self
+            .send_request(
+                Method::POST,
+                &format!("{}/v1/messages", self.base_url),
+                Some(serde_json::to_value(request)?),
+            )
             .await?;
+        let mut s = server_sent_events(body).boxed();
 
         Ok(async_stream::stream! {
+            // The tool use being streamed: its id, its name and its partial JSON input.
             let tool_use = Arc::new(Mutex::new((None, None, String::new())));
+            // The input tokens are reported when the message starts, the output tokens when
+            // it ends.
+            let mut usage = Usage::default();
 
             while let Some(item) = s.next().await {
                 match item {
                     Ok(event) => match event {
-                        Event::Ping => continue,
-                        Event::MessageStart { message } => {
+                        MessagesEvent::Ping => continue,
+                        MessagesEvent::MessageStart { message } => {
+                            usage = message.usage.into();
+
                             let content = message
-                                .message_response
                                 .content
-                                .into_iter()
-                                .map(|c| anthropic_content_to_ferrochain(&c))
-                                .collect::<Vec<Content>>();
+                                .iter()
+                                .filter_map(|part| anthropic_content_to_ferrochain(part).transpose())
+                                .collect::<Result<Vec<Content>>>();
 
-                            yield Ok(StreamEventEnvelope{ index: 0, event: StreamEvent::Start {
+                            yield content.map(|content| StreamEventEnvelope { index: 0, event: StreamEvent::Start {
                                 index: 0,
-                                model: message.message_response.model,
-                                role: message.message_response.role,
+                                model: message.model,
+                                role: message.role,
                                 inner: content,
                             }})
                         }
-                        Event::ContentBlockStart {
-                            index,
-                            content_block: delta,
-                        } | Event::ContentBlockDelta { index, delta } => match delta {
+                        MessagesEvent::ContentBlockStart { index, content_block } => match content_block {
                             ContentPart::ToolUse { id, name, .. } => {
-                                tool_use.lock().await.0.replace(id);
-                                tool_use.lock().await.1.replace(name);
-                                continue
-                            }
-                            ContentPart::InputJsonDelta { partial_json } => {
                                 let mut guard = tool_use.lock().await;
-                                guard.2.push_str(&partial_json.to_string());
-                                match serde_json::from_str(&guard.2) {
-                                    Ok(value) => {
-                                        yield Ok(StreamEventEnvelope { index: 0, event: StreamEvent::Delta {
-                                            index,
-                                            inner: vec![Content::ToolUse(ToolUse {
-                                                id: guard.0.take().unwrap(),
-                                                tool: guard.1.take().unwrap(),
-                                                input: value,
-                                            })],
-                                        }});
-
-                                        guard.2.clear();
-                                    }
-                                    _ => {
-                                        continue;
-                                    }
-                                }
+                                *guard = (Some(id), Some(name), String::new());
+                            }
+                            _ => if let Some(content) = anthropic_content_to_ferrochain(&content_block).transpose() {
+                                yield content.map(|content| StreamEventEnvelope { index: 0, event: StreamEvent::Delta {
+                                    index,
+                                    inner: vec![content],
+                                }})
+                            },
+                        },
+                        MessagesEvent::ContentBlockDelta { index, delta } => match delta {
+                            ContentDelta::InputJson { partial_json } => {
+                                tool_use.lock().await.2.push_str(&partial_json);
+                            }
+                            _ => if let Some(content) = anthropic_delta_to_ferrochain(delta).transpose() {
+                                yield content.map(|content| StreamEventEnvelope { index: 0, event: StreamEvent::Delta {
+                                    index,
+                                    inner: vec![content],
+                                }})
                             },
-                            _ => yield Ok(StreamEventEnvelope { index: 0, event: StreamEvent::Delta {
-                                index,
-                                inner: vec![anthropic_content_to_ferrochain(&delta)],
-                            }}),
                         },
-                        // Event::ContentBlockDelta { index, delta } => match delta {
-                        //     ContentPart::ToolUse { id, name, input } => {
-                        //         tool_use.lock().await.0.replace(id);
-                        //         tool_use.lock().await.1.replace(name);
-                        //         tool_use.lock().await.2.push_str(&serde_json::to_string(&input)?);
-                        //         continue
-                        //     }
-                        //     ContentPart::InputJsonDelta { partial_json } => {
-                        //         let mut guard = tool_use.lock().await;
-                        //         guard.2.push_str(&serde_json::to_string(&partial_json)?);
-                        //         match serde_json::from_str(&guard.2) {
-                        //             Ok(value) => {
-                        //                 yield Ok(StreamEvent::Delta {
-                        //                     index,
-                        //                     inner: vec![Content::ToolUse {
-                        //                         id: guard.0.take().unwrap(),
-                        //                         tool: guard.1.take().unwrap(),
-                        //                         input: value,
-                        //                     }],
-                        //                 });
-
-                        //                 guard.2.clear();
-                        //             }
-                        //             _ => continue,
-                        //         }
-                        //     },
-                        //     _ => yield Ok(StreamEvent::Delta {
-                        //         index,
-                        //         inner: vec![anthropic_content_to_ferrochain(&delta)],
-                        //     }),
-                        // },
-                        Event::ContentBlockStop { .. } => continue,
-                        Event::MessageDelta { delta, .. } => yield Ok(StreamEventEnvelope { index: 0, event: StreamEvent::End {
-                            stop_reason: format!("{:?}", delta.stop_reason),
-                        }}),
-                        Event::MessageStop => continue,
-                        Event::Error(err) => yield Err(anyhow!("{:?}", err)),
+                        MessagesEvent::ContentBlockStop { index } => {
+                            let mut guard = tool_use.lock().await;
+                            let (Some(id), Some(tool)) = (guard.0.take(), guard.1.take()) else {
+                                continue;
+                            };
+                            let input = std::mem::take(&mut guard.2);
+
+                            // Tools without input may stream no JSON at all.
+                            let input = if input.trim().is_empty() {
+                                Ok(Value::Object(Default::default()))
+                            } else {
+                                serde_json::from_str(&input).map_err(|err| {
+                                    anyhow!("invalid input for tool `{}`: {}", tool, err)
+                                })
+                            };
+                            yield input.map(|input| StreamEventEnvelope { index: 0, event: StreamEvent::Delta {
+                                index,
+                                inner: vec![Content::ToolUse(ToolUse { id, tool, input })],
+                            }})
+                        }
+                        MessagesEvent::MessageDelta { delta, usage: delta_usage } => {
+                            usage.output_tokens = delta_usage.output_tokens;
+                            yield Ok(StreamEventEnvelope { index: 0, event: StreamEvent::End {
+                                stop_reason: delta.stop_reason.unwrap_or_default(),
+                                usage: Some(usage),
+                            }})
+                        }
+                        MessagesEvent::MessageStop | MessagesEvent::Unknown => continue,
+                        MessagesEvent::Error { error } => {
+                            yield Err(anyhow!("{}: {}", error.kind, error.message))
+                        }
                     },
                     Err(err) => yield Err(err),
                 }
@@ -260,71 +692,1440 @@ impl Completion for AnthropicCompletion {
     }
 }
 
-fn anthropic_content_to_ferrochain(content: &ContentPart) -> Content {
-    match content {
-        ContentPart::Text { text } | ContentPart::TextDelta { text } => Content::Text {
+/// The body of a request to the Messages API.
+#[derive(serde::Serialize)]
+struct CreateMessageRequest {
+    model: String,
+    messages: Vec<AnthropicMessage>,
+    max_tokens: u32,
+    metadata: Metadata,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<Vec<ContentPart>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<Tool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<AnthropicToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<ThinkingConfig>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+/// A message of the conversation, which is either from the user or from the assistant once
+/// system messages are lifted to the system prompt.
+#[derive(serde::Serialize)]
+struct AnthropicMessage {
+    role: Role,
+    content: Vec<ContentPart>,
+}
+
+#[derive(serde::Serialize)]
+struct Metadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user_id: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum CacheControl {
+    Ephemeral,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ThinkingConfig {
+    Enabled { budget_tokens: u32 },
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicToolChoice {
+    Any,
+    None,
+    Tool { name: String },
+}
+
+#[derive(serde::Serialize)]
+struct Tool {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    /// The JSON schema of the input, whose root is an object.
+    input_schema: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<CacheControl>,
+}
+
+/// A content block, as sent in requests and received in responses.
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart {
+    Text {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Image {
+        source: ImageBlockSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Document {
+        source: DocumentBlockSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Thinking {
+        thinking: String,
+        #[serde(default)]
+        signature: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: Vec<ContentPart>,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    /// A block of a type this client doesn't know about, which is skipped.
+    #[serde(other, skip_serializing)]
+    Unknown,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct ImageBlockSource {
+    #[serde(rename = "type")]
+    kind: String,
+    media_type: String,
+    data: String,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum DocumentBlockSource {
+    Base64 { media_type: String, data: String },
+    Text { media_type: String, data: String },
+}
+
+/// An event of a streamed response.
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum MessagesEvent {
+    Ping,
+    MessageStart {
+        message: MessageResponse,
+    },
+    ContentBlockStart {
+        index: u64,
+        content_block: ContentPart,
+    },
+    ContentBlockDelta {
+        index: u64,
+        delta: ContentDelta,
+    },
+    ContentBlockStop {
+        index: u64,
+    },
+    MessageDelta {
+        delta: MessageDelta,
+        #[serde(default)]
+        usage: AnthropicUsage,
+    },
+    MessageStop,
+    Error {
+        error: AnthropicError,
+    },
+    /// An event of a type this client doesn't know about, which is skipped.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "type")]
+enum ContentDelta {
+    #[serde(rename = "text_delta")]
+    Text { text: String },
+    #[serde(rename = "input_json_delta")]
+    InputJson { partial_json: String },
+    #[serde(rename = "thinking_delta")]
+    Thinking { thinking: String },
+    #[serde(rename = "signature_delta")]
+    Signature { signature: String },
+    /// A delta of a type this client doesn't know about, which is skipped.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct MessageResponse {
+    model: String,
+    role: Role,
+    content: Vec<ContentPart>,
+    #[serde(default)]
+    usage: AnthropicUsage,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct MessageDelta {
+    stop_reason: Option<String>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct AnthropicUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    cache_creation_input_tokens: Option<u64>,
+    cache_read_input_tokens: Option<u64>,
+}
+
+impl From<AnthropicUsage> for Usage {
+    fn from(usage: AnthropicUsage) -> Self {
+        Usage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cache_read_tokens: usage.cache_read_input_tokens.unwrap_or_default(),
+            cache_write_tokens: usage.cache_creation_input_tokens.unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct AnthropicError {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+/// Parses the server-sent events of a streamed response, each carrying its JSON in `data`
+/// lines and ending with a blank line.
+fn server_sent_events(body: AsyncBody) -> impl Stream<Item = Result<MessagesEvent>> {
+    async_stream::try_stream! {
+        let mut lines = BufReader::new(body).lines();
+        let mut data = String::new();
+
+        while let Some(line) = lines.next().await {
+            let line = line?;
+            if line.is_empty() {
+                if !data.is_empty() {
+                    yield serde_json::from_str::<MessagesEvent>(&data)?;
+                    data.clear();
+                }
+                continue;
+            }
+
+            // The `event` lines repeat the type found in the data.
+            if let Some(value) = line.strip_prefix("data:") {
+                if !data.is_empty() {
+                    data.push('\n');
+                }
+                data.push_str(value.strip_prefix(' ').unwrap_or(value));
+            }
+        }
+
+        if !data.is_empty() {
+            yield serde_json::from_str::<MessagesEvent>(&data)?;
+        }
+    }
+}
+
+/// A batch of the Message Batches API.
+#[derive(serde::Deserialize)]
+struct MessageBatch {
+    id: String,
+    processing_status: BatchState,
+    request_counts: RequestCounts,
+    results_url: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct RequestCounts {
+    processing: u64,
+    succeeded: u64,
+    errored: u64,
+    canceled: u64,
+    expired: u64,
+}
+
+/// A line of the results of a batch.
+#[derive(serde::Deserialize)]
+struct MessageBatchResult {
+    custom_id: String,
+    result: MessageBatchOutcome,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum MessageBatchOutcome {
+    Succeeded { message: MessageBatchResponse },
+    Errored { error: Value },
+    Canceled,
+    Expired,
+}
+
+#[derive(serde::Deserialize)]
+struct MessageBatchResponse {
+    content: Vec<ContentPart>,
+}
+
+impl AnthropicCompletion {
+    async fn message_batch(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<Value>,
+    ) -> Result<MessageBatch> {
+        let mut data = vec![];
+        self.send_request(method, url, body)
+            .await?
+            .read_to_end(&mut data)
+            .await?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+/// Completes batches through the Message Batches API, which processes them within a day at a
+/// lower cost.
+///
+/// The requests of a batch are built like those of [`Completion::complete_with_options`].
+#[ferrochain::async_trait]
+impl BatchCompletion for AnthropicCompletion {
+    async fn submit(&self, requests: Vec<BatchRequest>) -> Result<String> {
+        let mut batch_requests = Vec::with_capacity(requests.len());
+        for request in requests {
+            batch_requests.push(json!({
+                "custom_id": request.custom_id,
+                "params": self
+                    .create_message_request(request.messages, request.options)
+                    .await?,
+            }));
+        }
+
+        let batch = self
+            .message_batch(
+                Method::POST,
+                &format!("{}/v1/messages/batches", self.base_url),
+                Some(json!({ "requests": batch_requests })),
+            )
+            .await?;
+        Ok(batch.id)
+    }
+
+    async fn status(&self, batch_id: &str) -> Result<BatchStatus> {
+        let batch = self
+            .message_batch(
+                Method::GET,
+                &format!("{}/v1/messages/batches/{}", self.base_url, batch_id),
+                None,
+            )
+            .await?;
+        Ok(BatchStatus {
+            state: batch.processing_status,
+            processing: batch.request_counts.processing,
+            succeeded: batch.request_counts.succeeded,
+            errored: batch.request_counts.errored,
+            canceled: batch.request_counts.canceled,
+            expired: batch.request_counts.expired,
+        })
+    }
+
+    /// Streams the results of the batch as they are downloaded.
+    async fn results(&self, batch_id: &str) -> Result<BatchResults> {
+        let batch = self
+            .message_batch(
+                Method::GET,
+                &format!("{}/v1/messages/batches/{}", self.base_url, batch_id),
+                None,
+            )
+            .await?;
+        let results_url = match (batch.processing_status, batch.results_url) {
+            (BatchState::Ended, Some(results_url)) => results_url,
+            _ => return Err(anyhow!("the batch {} is still in progress", batch_id)),
+        };
+
+        let body = self.send_request(Method::GET, &results_url, None).await?;
+        Ok(BufReader::new(body)
+            .lines()
+            .map_err(ferrochain::anyhow::Error::from)
+            .try_filter(|line| future::ready(!line.trim().is_empty()))
+            .and_then(|line| future::ready(anthropic_batch_result_to_ferrochain(&line)))
+            .boxed())
+    }
+}
+
+fn anthropic_batch_result_to_ferrochain(line: &str) -> Result<BatchResult> {
+    let result: MessageBatchResult = serde_json::from_str(line)?;
+    Ok(BatchResult {
+        custom_id: result.custom_id,
+        outcome: match result.result {
+            MessageBatchOutcome::Succeeded { message } => BatchOutcome::Succeeded {
+                message: Message {
+                    role: Role::Assistant,
+                    content: message
+                        .content
+                        .iter()
+                        .filter_map(|part| anthropic_content_to_ferrochain(part).transpose())
+                        .collect::<Result<_>>()?,
+                    ..Default::default()
+                },
+            },
+            // Errors are wrapped in an error response: `{"type": "error", "error": {...}}`.
+            MessageBatchOutcome::Errored { error } => BatchOutcome::Errored {
+                error: error
+                    .pointer("/error/message")
+                    .and_then(Value::as_str)
+                    .map(Into::into)
+                    .unwrap_or_else(|| error.to_string()),
+            },
+            MessageBatchOutcome::Canceled => BatchOutcome::Canceled,
+            MessageBatchOutcome::Expired => BatchOutcome::Expired,
+        },
+    })
+}
+
+/// Moves the content of system messages to the system prompt, after `system`, and merges
+/// consecutive messages of the same role, since Anthropic expects alternating user and
+/// assistant messages. Tool messages are sent as user messages, which carry tool results.
+fn lift_system_messages(
+    system: Option<Vec<ContentPart>>,
+    messages: Vec<AnthropicMessage>,
+) -> (Option<Vec<ContentPart>>, Vec<AnthropicMessage>) {
+    let mut system = system;
+    let mut merged: Vec<AnthropicMessage> = vec![];
+
+    for message in messages {
+        let role = match message.role {
+            Role::System => {
+                system.get_or_insert_with(Vec::new).extend(message.content);
+                continue;
+            }
+            Role::Assistant => Role::Assistant,
+            Role::User | Role::Tool => Role::User,
+        };
+
+        match merged.last_mut() {
+            Some(last) if last.role == role => last.content.extend(message.content),
+            _ => merged.push(AnthropicMessage {
+                role,
+                content: message.content,
+            }),
+        }
+    }
+
+    (system, merged)
+}
+
+/// Converts a content block, skipping the blocks of unknown types.
+fn anthropic_content_to_ferrochain(content: &ContentPart) -> Result<Option<Content>> {
+    Ok(Some(match content {
+        ContentPart::Text { text, .. } => Content::Text {
             text: text.to_owned(),
         },
-        ContentPart::Image { source } => Content::Image {
+        ContentPart::Image { source, .. } => Content::Image {
             source: ImageSource::Base64 {
                 data: source.data.to_owned(),
+                media_type: Some(source.media_type.to_owned()),
             },
         },
-        ContentPart::ToolUse { .. } | ContentPart::InputJsonDelta { .. } => unreachable!(),
-        ContentPart::ToolResult { .. } => todo!(),
+        ContentPart::Document { source, title, .. } => Content::Document {
+            source: match source {
+                DocumentBlockSource::Base64 { data, .. } => DocumentSource::Pdf {
+                    data: data.to_owned(),
+                },
+                DocumentBlockSource::Text { data, .. } => DocumentSource::Text {
+                    text: data.to_owned(),
+                },
+            },
+            title: title.to_owned(),
+        },
+        ContentPart::Thinking {
+            thinking,
+            signature,
+        } => Content::Thinking {
+            thinking: thinking.to_owned(),
+            // Streamed thinking starts empty, its signature following as a delta.
+            signature: Some(signature.to_owned()).filter(|signature| !signature.is_empty()),
+        },
+        ContentPart::RedactedThinking { data } => Content::RedactedThinking {
+            data: data.to_owned(),
+        },
+        ContentPart::ToolUse {
+            id, name, input, ..
+        } => Content::ToolUse(ToolUse {
+            id: id.to_owned(),
+            tool: name.to_owned(),
+            input: input.to_owned(),
+        }),
+        ContentPart::ToolResult {
+            tool_use_id,
+            content,
+            is_error,
+            ..
+        } => Content::ToolResult(ToolResult {
+            id: tool_use_id.to_owned(),
+            content: content
+                .iter()
+                .filter_map(|part| anthropic_content_to_ferrochain(part).transpose())
+                .collect::<Result<_>>()?,
+            is_error: *is_error,
+        }),
+        ContentPart::Unknown => return Ok(None),
+    }))
+}
+
+/// Converts a content delta, skipping the deltas of unknown types.
+fn anthropic_delta_to_ferrochain(delta: ContentDelta) -> Result<Option<Content>> {
+    Ok(Some(match delta {
+        ContentDelta::Text { text } => Content::Text { text },
+        ContentDelta::Thinking { thinking } => Content::Thinking {
+            thinking,
+            signature: None,
+        },
+        ContentDelta::Signature { signature } => Content::Thinking {
+            thinking: String::new(),
+            signature: Some(signature),
+        },
+        ContentDelta::InputJson { .. } => {
+            return Err(anyhow!("partial tool input outside of a tool use"))
+        }
+        ContentDelta::Unknown => return Ok(None),
+    }))
+}
+
+/// Converts the content of a message, marking a cache breakpoint on its last part when
+/// `cache_breakpoint` is set.
+fn ferrochain_contents_to_anthropic(
+    contents: Vec<Content>,
+    cache_breakpoint: bool,
+) -> Result<Vec<ContentPart>> {
+    let mut parts = vec![];
+    for content in contents {
+        match content {
+            // Thinking without a signature, such as another provider's, can't be sent back.
+            Content::Thinking {
+                signature: None, ..
+            } => {}
+            content => parts.push(ferrochain_content_to_anthropic(content)?),
+        }
     }
+    // Content with no part has nothing to cache.
+    if let (true, Some(last)) = (cache_breakpoint, parts.last_mut()) {
+        set_cache_control(last);
+    }
+    Ok(parts)
 }
 
-fn ferrochain_content_to_anthropic(content: Content) -> ContentPart {
-    match content {
-        ferrochain::message::Content::Text { text } => {
-            anthropic::messages::ContentPart::Text { text }
+fn set_cache_control(part: &mut ContentPart) {
+    match part {
+        ContentPart::Text { cache_control, .. }
+        | ContentPart::Image { cache_control, .. }
+        | ContentPart::Document { cache_control, .. }
+        | ContentPart::ToolUse { cache_control, .. }
+        | ContentPart::ToolResult { cache_control, .. } => {
+            *cache_control = Some(CacheControl::Ephemeral)
         }
-        ferrochain::message::Content::Image { source } => {
-            anthropic::messages::ContentPart::Image {
-                source: anthropic::messages::ImageSource {
+        // Other parts can't carry a breakpoint.
+        _ => {}
+    }
+}
+
+fn ferrochain_content_to_anthropic(content: Content) -> Result<ContentPart> {
+    Ok(match content {
+        Content::Text { text } => ContentPart::Text {
+            text,
+            cache_control: None,
+        },
+        Content::Image { source } => {
+            let media_type = source.media_type();
+            let ImageSource::Base64 { data, .. } = source else {
+                return Err(anyhow!("images must be inlined before being sent"));
+            };
+            let media_type =
+                media_type.ok_or_else(|| anyhow!("the media type of an image is unknown"))?;
+
+            if !IMAGE_MEDIA_TYPES.contains(&media_type.as_str()) {
+                return Err(anyhow!("unsupported image media type {}", media_type));
+            }
+
+            ContentPart::Image {
+                source: ImageBlockSource {
                     kind: "base64".to_string(),
-                    media_type: anthropic::messages::MediaType::ImagePng, // Assuming PNG for simplicity
-                    data: match source {
-                        ferrochain::message::ImageSource::Base64 { data } => data,
-                        ferrochain::message::ImageSource::Url { url } => url, // This is not correct, but we don't have a way to fetch the image data here
-                    },
+                    media_type,
+                    data,
                 },
+                cache_control: None,
             }
         }
-        ferrochain::message::Content::ToolUse(ferrochain::message::ToolUse { id, tool, input }) => {
-            anthropic::messages::ContentPart::ToolUse {
-                id,
-                name: tool,
-                input,
-            }
-        }
-        ferrochain::message::Content::ToolResult(ferrochain::message::ToolResult {
+        Content::Document { source, title } => ContentPart::Document {
+            source: match source {
+                DocumentSource::Pdf { data } => DocumentBlockSource::Base64 {
+                    media_type: "application/pdf".into(),
+                    data,
+                },
+                DocumentSource::Text { text } => DocumentBlockSource::Text {
+                    media_type: "text/plain".into(),
+                    data: text,
+                },
+            },
+            title,
+            cache_control: None,
+        },
+        Content::Thinking {
+            thinking,
+            signature,
+        } => ContentPart::Thinking {
+            thinking,
+            signature: signature.ok_or_else(|| {
+                anyhow!("thinking can only be sent back along with its signature")
+            })?,
+        },
+        Content::RedactedThinking { data } => ContentPart::RedactedThinking { data },
+        Content::ToolUse(ToolUse { id, tool, input }) => ContentPart::ToolUse {
             id,
-            content,
-        }) => anthropic::messages::ContentPart::ToolResult {
-            tool_use_id: id,
-            content,
+            name: tool,
+            input,
+            cache_control: None,
         },
+        Content::ToolResult(tool_result) => ContentPart::ToolResult {
+            tool_use_id: tool_result.id,
+            content: tool_result
+                .content
+                .into_iter()
+                .map(|content| match content {
+                    Content::Text { .. } | Content::Image { .. } | Content::Document { .. } => {
+                        ferrochain_content_to_anthropic(content)
+                    }
+                    _ => Err(anyhow!(
+                        "tool results can only contain text, images and documents"
+                    )),
+                })
+                .collect::<Result<_>>()?,
+            is_error: tool_result.is_error,
+            cache_control: None,
+        },
+    })
+}
+
+fn ferrochain_tool_choice_to_anthropic(tool_choice: ToolChoice) -> Option<AnthropicToolChoice> {
+    match tool_choice {
+        ToolChoice::Auto => None,
+        ToolChoice::Any => Some(AnthropicToolChoice::Any),
+        ToolChoice::None => Some(AnthropicToolChoice::None),
+        ToolChoice::Tool { name } => Some(AnthropicToolChoice::Tool { name }),
+    }
+}
+
+/// Converts a tool, sending the whole schema of its input, so that its definitions, `oneOf`s
+/// and `additionalProperties` reach the model as they are.
+///
+/// [`ToolProvider::register`] only accepts tools whose input is an object, or `()`.
+fn ferrochain_tool_descriptor_to_anthropic(tool: ToolDescriptor) -> Result<Tool> {
+    let mut input_schema = serde_json::to_value(&tool.input)?;
+    if input_schema["type"] == "null" {
+        // Tools without input, such as those taking `()`, have a `null` schema, while
+        // Anthropic expects an object.
+        input_schema = json!({ "type": "object", "properties": {} });
+    } else if let Some(schema) = input_schema.as_object_mut() {
+        schema.remove("$schema");
+        schema.insert("type".into(), "object".into());
     }
+
+    Ok(Tool {
+        name: tool.name,
+        description: Some(tool.description),
+        input_schema,
+        cache_control: None,
+    })
 }
 
-fn ferrochain_tool_descriptor_to_anthropic(tool: ToolDescriptor) -> anthropic::messages::Tool {
-    let input_schema_value = serde_json::to_value(&tool.input).unwrap();
+#[cfg(test)]
+mod tests {
+    use ferrochain::tool::ExternalTool;
+
+    use super::*;
+
+    fn message(role: Role, text: &str) -> Message {
+        Message {
+            role,
+            content: vec![text.into()],
+            ..Default::default()
+        }
+    }
+
+    fn texts(content: &[Content]) -> Vec<&str> {
+        content
+            .iter()
+            .map(|content| match content {
+                Content::Text { text } => text.as_str(),
+                _ => panic!("expected text"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_model() {
+        let model: Model = serde_json::from_value(json!("claude-3-5-sonnet-20241022")).unwrap();
+        assert_eq!(model, Model::Claude35Sonnet20241022);
+
+        let model: Model = serde_json::from_value(json!("claude-next")).unwrap();
+        assert_eq!(model, Model::Other("claude-next".into()));
+        assert_eq!(serde_json::to_value(&model).unwrap(), json!("claude-next"));
+        assert_eq!(
+            Model::Claude3Haiku20240307.to_string(),
+            "claude-3-haiku-20240307"
+        );
+    }
+
+    fn parts(role: Role, text: &str) -> AnthropicMessage {
+        AnthropicMessage {
+            role,
+            content: ferrochain_contents_to_anthropic(vec![text.into()], false).unwrap(),
+        }
+    }
+
+    fn part_texts(parts: &[ContentPart]) -> Vec<&str> {
+        parts
+            .iter()
+            .map(|part| match part {
+                ContentPart::Text { text, .. } => text.as_str(),
+                _ => panic!("expected text"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lift_system_messages() {
+        let (system, messages) = lift_system_messages(
+            Some(ferrochain_contents_to_anthropic(vec!["Be helpful".into()], false).unwrap()),
+            vec![
+                parts(Role::System, "Answer in French"),
+                parts(Role::User, "Hi"),
+                parts(Role::Tool, "42"),
+                parts(Role::Assistant, "Bonjour"),
+                parts(Role::Assistant, "Ça va ?"),
+            ],
+        );
+
+        assert_eq!(
+            part_texts(&system.unwrap()),
+            ["Be helpful", "Answer in French"]
+        );
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].role, Role::User);
+        assert_eq!(part_texts(&messages[0].content), ["Hi", "42"]);
+        assert_eq!(messages[1].role, Role::Assistant);
+        assert_eq!(part_texts(&messages[1].content), ["Bonjour", "Ça va ?"]);
+
+        let (system, _) = lift_system_messages(None, vec![parts(Role::User, "Hi")]);
+        assert!(system.is_none());
+    }
+
+    fn tool(input: Value) -> Tool {
+        ferrochain_tool_descriptor_to_anthropic(ToolDescriptor {
+            name: "tool".into(),
+            description: "A tool".into(),
+            input: serde_json::from_value(input).unwrap(),
+            output: serde_json::from_value(json!({ "type": "string" })).unwrap(),
+            external: false,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_tool_without_input() {
+        assert_eq!(
+            tool(json!({ "type": "null" })).input_schema,
+            json!({ "type": "object", "properties": {} })
+        );
+    }
+
+    #[test]
+    fn test_tool_input_schemas() {
+        // Inputs made of alternatives are sent whole, as an object.
+        let shapes = json!({
+            "oneOf": [
+                {
+                    "type": "object",
+                    "properties": { "radius": { "type": "number" } },
+                    "required": ["radius"],
+                },
+                {
+                    "type": "object",
+                    "properties": { "side": { "type": "number" } },
+                    "required": ["side"],
+                },
+            ],
+        });
+        let mut expected = shapes.clone();
+        expected["type"] = json!("object");
+        assert_eq!(tool(shapes).input_schema, expected);
+
+        // Definitions are kept, so that references still resolve.
+        let line = json!({
+            "type": "object",
+            "properties": {
+                "from": { "$ref": "#/definitions/Point" },
+                "to": { "$ref": "#/definitions/Point" },
+            },
+            "required": ["from", "to"],
+            "additionalProperties": false,
+            "definitions": {
+                "Point": {
+                    "type": "object",
+                    "properties": { "x": { "type": "number" }, "y": { "type": "number" } },
+                },
+            },
+        });
+        let mut with_meta_schema = line.clone();
+        with_meta_schema["$schema"] = json!("http://json-schema.org/draft-07/schema#");
+        assert_eq!(tool(with_meta_schema).input_schema, line);
+    }
+
+    #[test]
+    fn test_tool_results() {
+        let part = ferrochain_content_to_anthropic(Content::ToolResult(ToolResult {
+            id: "toolu_1".into(),
+            content: vec![
+                "Rendering failed".into(),
+                Content::Image {
+                    source: ImageSource::Base64 {
+                        data: "iVBORw0KGgo=".into(),
+                        media_type: Some("image/png".into()),
+                    },
+                },
+            ],
+            is_error: true,
+        }))
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&part).unwrap(),
+            json!({
+                "type": "tool_result",
+                "tool_use_id": "toolu_1",
+                "content": [
+                    { "type": "text", "text": "Rendering failed" },
+                    {
+                        "type": "image",
+                        "source": {
+                            "type": "base64",
+                            "media_type": "image/png",
+                            "data": "iVBORw0KGgo=",
+                        },
+                    },
+                ],
+                "is_error": true,
+            })
+        );
+        assert!(matches!(
+            anthropic_content_to_ferrochain(&part).unwrap().unwrap(),
+            Content::ToolResult(ToolResult { is_error: true, content, .. }) if content.len() == 2
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cache_breakpoints() {
+        let completion = AnthropicCompletion::builder()
+            .with_http_client(Arc::new(MockServer::default()))
+            .with_api_key("key")
+            .with_model(serde_json::from_value(json!("claude-3-5-sonnet-20241022")).unwrap())
+            .with_max_tokens(1024)
+            .with_system(vec!["Be helpful".into()])
+            .with_cache_system(true)
+            .build()
+            .unwrap();
+
+        let request = completion
+            .create_message_request(
+                vec![
+                    message(Role::System, "Answer in French"),
+                    Message {
+                        cache_breakpoint: true,
+                        ..message(Role::User, "Hi")
+                    },
+                    message(Role::Tool, "42"),
+                ],
+                CompletionOptions::default(),
+            )
+            .await
+            .unwrap();
+        let request = serde_json::to_value(request).unwrap();
+
+        let ephemeral = json!({ "type": "ephemeral" });
+        assert_eq!(
+            request["system"],
+            json!([
+                { "type": "text", "text": "Be helpful", "cache_control": ephemeral },
+                { "type": "text", "text": "Answer in French" },
+            ])
+        );
+        assert_eq!(
+            request["messages"],
+            json!([{
+                "role": "user",
+                "content": [
+                    { "type": "text", "text": "Hi", "cache_control": ephemeral },
+                    { "type": "text", "text": "42" },
+                ],
+            }])
+        );
+
+        // The breakpoint isn't stored along with the message.
+        let stored = serde_json::to_value(Message {
+            cache_breakpoint: true,
+            ..message(Role::User, "Hi")
+        })
+        .unwrap();
+        assert!(stored.get("cache_breakpoint").is_none());
+
+        let usage: AnthropicUsage = serde_json::from_value(json!({
+            "input_tokens": 10,
+            "output_tokens": 1,
+            "cache_creation_input_tokens": 2048,
+            "cache_read_input_tokens": 1024,
+        }))
+        .unwrap();
+        assert_eq!(
+            Usage::from(usage),
+            Usage {
+                input_tokens: 10,
+                output_tokens: 1,
+                cache_read_tokens: 1024,
+                cache_write_tokens: 2048,
+            }
+        );
+    }
+
+    #[test]
+    fn test_streamed_thinking() {
+        let start = anthropic_content_to_ferrochain(&ContentPart::Thinking {
+            thinking: String::new(),
+            signature: String::new(),
+        })
+        .unwrap()
+        .unwrap();
+        assert!(matches!(
+            start,
+            Content::Thinking {
+                signature: None,
+                ..
+            }
+        ));
+
+        let signature = anthropic_delta_to_ferrochain(
+            serde_json::from_value(json!({
+                "type": "signature_delta",
+                "signature": "signature",
+            }))
+            .unwrap(),
+        )
+        .unwrap()
+        .unwrap();
+        assert!(matches!(
+            signature,
+            Content::Thinking { thinking, signature: Some(signature) }
+                if thinking.is_empty() && signature == "signature"
+        ));
+    }
 
-    anthropic::messages::Tool {
-        name: tool.name.clone(),
-        description: Some(tool.description.clone()),
-        input_schema: ToolInputSchema {
-            kind: input_schema_value["type"].as_str().unwrap().to_string(),
-            properties: input_schema_value["properties"].clone(),
-            required: input_schema_value["required"]
-                .as_array()
-                .as_ref()
+    #[tokio::test]
+    async fn test_unknown_events() {
+        let body = [
+            json!({
+                "type": "content_block_start",
+                "index": 0,
+                "content_block": { "type": "redacted_thinking", "data": "encrypted" },
+            }),
+            json!({
+                "type": "content_block_start",
+                "index": 1,
+                "content_block": { "type": "unknown_block", "text": "?" },
+            }),
+            json!({ "type": "unknown_event", "index": 1 }),
+        ]
+        .iter()
+        .map(|event| format!("data: {}\n\n", event))
+        .collect::<String>();
+        let events = server_sent_events(AsyncBody::from(body))
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert!(matches!(events[2], MessagesEvent::Unknown));
+
+        let contents = events
+            .iter()
+            .filter_map(|event| match event {
+                MessagesEvent::ContentBlockStart { content_block, .. } => {
+                    Some(anthropic_content_to_ferrochain(content_block).unwrap())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            &contents[..],
+            [Some(Content::RedactedThinking { data }), None] if data == "encrypted"
+        ));
+
+        // Redacted thinking is sent back as it was received.
+        let part = ferrochain_content_to_anthropic(contents[0].clone().unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_value(part).unwrap(),
+            json!({ "type": "redacted_thinking", "data": "encrypted" })
+        );
+    }
+
+    #[test]
+    fn test_thinking_budget() {
+        // The budget is checked before the model, which these builders lack.
+        let error = |builder: AnthropicCompletionBuilder| {
+            builder
+                .with_api_key("key")
+                .with_max_tokens(4096)
+                .build()
+                .err()
                 .unwrap()
-                .into_iter()
-                .map(|e| serde_json::from_value(e.to_owned()).unwrap())
-                .collect::<Vec<String>>(),
-        },
+                .to_string()
+        };
+
+        let builder = AnthropicCompletion::builder;
+        assert!(error(builder().with_thinking_budget(512)).contains("at least 1024"));
+        assert!(error(builder().with_thinking_budget(4096)).contains("less than max_tokens"));
+        assert!(
+            error(builder().with_thinking_budget(2048).with_temperature(0.5))
+                .contains("temperature")
+        );
+        assert_eq!(
+            error(builder().with_thinking_budget(2048)),
+            "model is required"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_thinking_request() {
+        let completion = AnthropicCompletion::builder()
+            .with_http_client(Arc::new(MockServer::default()))
+            .with_api_key("key")
+            .with_model(serde_json::from_value(json!("claude-3-5-sonnet-20241022")).unwrap())
+            .with_max_tokens(4096)
+            .with_thinking_budget(2048)
+            .build()
+            .unwrap();
+
+        let request = completion
+            .create_message_request(
+                vec![
+                    message(Role::User, "Hi"),
+                    Message {
+                        role: Role::Assistant,
+                        content: vec![
+                            Content::Thinking {
+                                thinking: "The user greets me.".into(),
+                                signature: Some("signature".into()),
+                            },
+                            "Hello!".into(),
+                        ],
+                        ..Default::default()
+                    },
+                    message(Role::User, "How are you?"),
+                ],
+                CompletionOptions::default(),
+            )
+            .await
+            .unwrap();
+        let request = serde_json::to_value(request).unwrap();
+
+        assert_eq!(
+            request["thinking"],
+            json!({ "type": "enabled", "budget_tokens": 2048 })
+        );
+        assert_eq!(
+            request["messages"][1]["content"][0],
+            json!({
+                "type": "thinking",
+                "thinking": "The user greets me.",
+                "signature": "signature",
+            })
+        );
+        assert!(request.get("temperature").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_request_options() {
+        let completion = AnthropicCompletion::builder()
+            .with_http_client(Arc::new(MockServer::default()))
+            .with_api_key("key")
+            .with_model(serde_json::from_value(json!("claude-3-5-sonnet-20241022")).unwrap())
+            .with_max_tokens(1024)
+            .with_user_id("user-1")
+            .build()
+            .unwrap();
+        let request = |options: CompletionOptions| {
+            completion.create_message_request(vec![message(Role::User, "Hi")], options)
+        };
+
+        let default = serde_json::to_value(request(Default::default()).await.unwrap()).unwrap();
+        assert_eq!(default["metadata"], json!({ "user_id": "user-1" }));
+        assert_eq!(default["max_tokens"], 1024);
+
+        let overridden = serde_json::to_value(
+            request(CompletionOptions::default().with_user_id("user-2"))
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(overridden["metadata"], json!({ "user_id": "user-2" }));
+
+        assert!(
+            request(CompletionOptions::default().with_max_tokens(usize::MAX))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disabled_tools() {
+        let mut tool_provider = ToolProvider::new();
+        tool_provider
+            .register(ExternalTool::<(), String>::new("now", "Tells the time"))
+            .unwrap();
+        let completion = AnthropicCompletion::builder()
+            .with_http_client(Arc::new(MockServer::default()))
+            .with_api_key("key")
+            .with_model(serde_json::from_value(json!("claude-3-5-sonnet-20241022")).unwrap())
+            .with_max_tokens(1024)
+            .with_tool_provider(tool_provider)
+            .build()
+            .unwrap();
+
+        let request = completion
+            .create_message_request(
+                vec![message(Role::User, "Hi")],
+                CompletionOptions::default()
+                    .with_tools(ToolSelection::default().with_choice(ToolChoice::None)),
+            )
+            .await
+            .unwrap();
+        let request = serde_json::to_value(request).unwrap();
+
+        assert_eq!(request["tools"][0]["name"], "now");
+        assert_eq!(request["tool_choice"], json!({ "type": "none" }));
+    }
+
+    /// Serves the Messages and Message Batches APIs, recording the requests sent to them.
+    #[derive(Clone, Default)]
+    struct MockServer {
+        requests: Arc<std::sync::Mutex<Vec<Value>>>,
+    }
+
+    impl HttpClient for MockServer {
+        fn send(
+            &self,
+            request: Request<AsyncBody>,
+        ) -> ferrochain::futures::future::BoxFuture<'static, Result<http_client::Response<AsyncBody>>>
+        {
+            let requests = self.requests.clone();
+            Box::pin(async move {
+                assert_eq!(request.headers()["x-api-key"], "key");
+                let (method, path) = (request.method().clone(), request.uri().path().to_string());
+                let mut body = vec![];
+                request.into_body().read_to_end(&mut body).await?;
+                if !body.is_empty() {
+                    requests
+                        .lock()
+                        .unwrap()
+                        .push(serde_json::from_slice(&body)?);
+                }
+
+                let body = match (method, path.as_str()) {
+                    (Method::POST, "/v1/messages") => [
+                        json!({
+                            "type": "message_start",
+                            "message": {
+                                "id": "msg_1",
+                                "type": "message",
+                                "role": "assistant",
+                                "model": "claude-3-5-sonnet-20241022",
+                                "content": [],
+                                "stop_reason": null,
+                                "usage": { "input_tokens": 10, "output_tokens": 1 },
+                            },
+                        }),
+                        json!({ "type": "ping" }),
+                        json!({
+                            "type": "content_block_start",
+                            "index": 0,
+                            "content_block": { "type": "text", "text": "" },
+                        }),
+                        json!({
+                            "type": "content_block_delta",
+                            "index": 0,
+                            "delta": { "type": "text_delta", "text": "Let me check." },
+                        }),
+                        json!({ "type": "content_block_stop", "index": 0 }),
+                        json!({
+                            "type": "content_block_start",
+                            "index": 1,
+                            "content_block": {
+                                "type": "tool_use",
+                                "id": "toolu_1",
+                                "name": "weather",
+                                "input": {},
+                            },
+                        }),
+                        json!({
+                            "type": "content_block_delta",
+                            "index": 1,
+                            "delta": { "type": "input_json_delta", "partial_json": "{\"city\": " },
+                        }),
+                        json!({
+                            "type": "content_block_delta",
+                            "index": 1,
+                            "delta": { "type": "input_json_delta", "partial_json": "\"Paris\"}" },
+                        }),
+                        json!({ "type": "content_block_stop", "index": 1 }),
+                        json!({
+                            "type": "message_delta",
+                            "delta": { "stop_reason": "tool_use", "stop_sequence": null },
+                            "usage": { "output_tokens": 25 },
+                        }),
+                        json!({ "type": "message_stop" }),
+                    ]
+                    .iter()
+                    .map(|event| {
+                        format!(
+                            "event: {}\ndata: {}\n\n",
+                            event["type"].as_str().unwrap(),
+                            event
+                        )
+                    })
+                    .collect(),
+                    (Method::POST, "/v1/messages/batches") => json!({
+                        "id": "msgbatch_1",
+                        "processing_status": "in_progress",
+                        "request_counts": {
+                            "processing": 2,
+                            "succeeded": 0,
+                            "errored": 0,
+                            "canceled": 0,
+                            "expired": 0,
+                        },
+                        "results_url": null,
+                    })
+                    .to_string(),
+                    (Method::GET, "/v1/messages/batches/msgbatch_1") => json!({
+                        "id": "msgbatch_1",
+                        "processing_status": "ended",
+                        "request_counts": {
+                            "processing": 0,
+                            "succeeded": 1,
+                            "errored": 1,
+                            "canceled": 0,
+                            "expired": 0,
+                        },
+                        "results_url": "http://mock/v1/messages/batches/msgbatch_1/results",
+                    })
+                    .to_string(),
+                    (Method::GET, "/v1/messages/batches/msgbatch_1/results") => [
+                        json!({
+                            "custom_id": "greeting",
+                            "result": {
+                                "type": "succeeded",
+                                "message": {
+                                    "id": "msg_1",
+                                    "type": "message",
+                                    "role": "assistant",
+                                    "model": "claude-3-5-sonnet-20241022",
+                                    "content": [{ "type": "text", "text": "Hello!" }],
+                                    "stop_reason": "end_turn",
+                                    "usage": { "input_tokens": 10, "output_tokens": 2 },
+                                },
+                            },
+                        }),
+                        json!({
+                            "custom_id": "broken",
+                            "result": {
+                                "type": "errored",
+                                "error": {
+                                    "type": "error",
+                                    "error": {
+                                        "type": "invalid_request_error",
+                                        "message": "max_tokens is too large",
+                                    },
+                                },
+                            },
+                        }),
+                    ]
+                    .iter()
+                    .map(|line| line.to_string() + "\n")
+                    .collect(),
+                    _ => {
+                        return Ok(http_client::Response::builder()
+                            .status(404)
+                            .body(AsyncBody::from("not found".to_string()))?)
+                    }
+                };
+
+                Ok(http_client::Response::builder()
+                    .status(200)
+                    .body(AsyncBody::from(body))?)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_message_batches() {
+        let server = MockServer::default();
+        let completion = AnthropicCompletion::builder()
+            .with_http_client(Arc::new(server.clone()))
+            .with_api_key("key")
+            .with_base_url("http://mock/")
+            .with_model(serde_json::from_value(json!("claude-3-5-sonnet-20241022")).unwrap())
+            .with_max_tokens(1024)
+            .build()
+            .unwrap();
+
+        let batch_id = completion
+            .submit(vec![
+                BatchRequest::new("greeting", vec![message(Role::User, "Hi")]),
+                BatchRequest::new("broken", vec![message(Role::User, "Hi")])
+                    .with_options(CompletionOptions::default().with_max_tokens(1_000_000)),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(batch_id, "msgbatch_1");
+
+        let submitted = server.requests.lock().unwrap().clone();
+        let requests = &submitted[0]["requests"];
+        assert_eq!(requests[0]["custom_id"], "greeting");
+        assert_eq!(requests[0]["params"]["max_tokens"], 1024);
+        assert_eq!(requests[1]["params"]["max_tokens"], 1_000_000);
+
+        assert_eq!(
+            completion.status(&batch_id).await.unwrap(),
+            BatchStatus {
+                state: BatchState::Ended,
+                succeeded: 1,
+                errored: 1,
+                ..Default::default()
+            }
+        );
+
+        let results = completion
+            .results(&batch_id)
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        match &results[0] {
+            BatchResult {
+                custom_id,
+                outcome: BatchOutcome::Succeeded { message },
+            } => {
+                assert_eq!(custom_id, "greeting");
+                assert_eq!(message.role, Role::Assistant);
+                assert_eq!(texts(&message.content), ["Hello!"]);
+            }
+            _ => panic!("expected a successful result"),
+        }
+        match &results[1] {
+            BatchResult {
+                custom_id,
+                outcome: BatchOutcome::Errored { error },
+            } => {
+                assert_eq!(custom_id, "broken");
+                assert_eq!(error, "max_tokens is too large");
+            }
+            _ => panic!("expected an errored result"),
+        }
+
+        assert!(completion.status("msgbatch_2").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_streamed_message() {
+        let server = MockServer::default();
+        let completion = AnthropicCompletion::builder()
+            .with_http_client(Arc::new(server.clone()))
+            .with_api_key("key")
+            .with_base_url("http://mock")
+            .with_model(serde_json::from_value(json!("claude-3-5-sonnet-20241022")).unwrap())
+            .with_max_tokens(1024)
+            .build()
+            .unwrap();
+
+        let events = completion
+            .complete(vec![message(Role::User, "What's the weather in Paris?")])
+            .await
+            .unwrap()
+            .try_collect::<Vec<StreamEventEnvelope<Vec<Content>>>>()
+            .await
+            .unwrap();
+
+        let request = server.requests.lock().unwrap()[0].clone();
+        assert_eq!(request["stream"], true);
+        assert_eq!(request["max_tokens"], 1024);
+        assert_eq!(
+            request["messages"],
+            json!([{
+                "role": "user",
+                "content": [{ "type": "text", "text": "What's the weather in Paris?" }],
+            }])
+        );
+
+        let contents = events
+            .iter()
+            .filter_map(|envelope| match &envelope.event {
+                StreamEvent::Delta { inner, .. } => Some(serde_json::to_value(inner).unwrap()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(contents.len(), 3);
+        assert_eq!(
+            contents[2],
+            serde_json::to_value(vec![Content::ToolUse(ToolUse {
+                id: "toolu_1".into(),
+                tool: "weather".into(),
+                input: json!({ "city": "Paris" }),
+            })])
+            .unwrap()
+        );
+        match &events[events.len() - 1].event {
+            StreamEvent::End { stop_reason, usage } => {
+                assert_eq!(stop_reason, "tool_use");
+                assert_eq!(
+                    usage.unwrap(),
+                    Usage {
+                        input_tokens: 10,
+                        output_tokens: 25,
+                        ..Default::default()
+                    }
+                );
+            }
+            _ => panic!("expected the end of the message"),
+        }
     }
 }
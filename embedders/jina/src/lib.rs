@@ -2,12 +2,14 @@ use std::sync::Arc;
 
 use ferrochain::{
     anyhow::{anyhow, Result},
+    config::{ComponentFactory, Components},
     embedding::{Embedder, Embedding},
 };
 use http_client::HttpClient;
 use jina_sdk::{
     EmbeddingType, EmbeddingsInput, EmbeddingsModel, EmbeddingsRequest, Jina, JinaBuilder,
 };
+use serde_json::Value;
 
 pub struct JinaEmbedder {
     client: Jina,
@@ -109,3 +111,55 @@ impl JinaEmbedderBuilder {
         })
     }
 }
+
+/// Builds [`JinaEmbedder`]s out of their configuration, see [`ferrochain::config`].
+#[derive(Clone, Default)]
+pub struct JinaEmbedderFactory {
+    http_client: Option<Arc<dyn HttpClient>>,
+}
+
+impl JinaEmbedderFactory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_http_client(mut self, http_client: Arc<dyn HttpClient>) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JinaEmbedderParams {
+    api_key: String,
+    base_url: Option<String>,
+    model: EmbeddingsModel,
+    normalized: Option<bool>,
+    embedding_type: Option<EmbeddingType>,
+}
+
+#[ferrochain::async_trait]
+impl ComponentFactory<dyn Embedder> for JinaEmbedderFactory {
+    async fn build(&self, params: Value, _: &Components) -> Result<Box<dyn Embedder>> {
+        let params: JinaEmbedderParams = serde_json::from_value(params)?;
+
+        let mut builder = JinaEmbedder::builder()
+            .with_api_key(params.api_key)
+            .with_model(params.model);
+        if let Some(http_client) = self.http_client.clone() {
+            builder = builder.with_http_client(http_client);
+        }
+        if let Some(base_url) = params.base_url {
+            builder = builder.with_base_url(base_url);
+        }
+        if let Some(normalized) = params.normalized {
+            builder = builder.with_normalized(normalized);
+        }
+        if let Some(embedding_type) = params.embedding_type {
+            builder = builder.with_embedding_type(embedding_type);
+        }
+
+        Ok(Box::new(builder.build()?))
+    }
+}
@@ -2,9 +2,11 @@ use std::sync::Arc;
 
 use ferrochain::{
     anyhow::{anyhow, Result},
+    config::{ComponentFactory, Components},
     embedding::{Embedder, Embedding},
 };
 use http_client::HttpClient;
+use serde_json::Value;
 use voyageai_sdk::{EmbeddingInput, EmbeddingRequest, VoyageAi, VoyageAiBuilder};
 pub use voyageai_sdk::{EmbeddingInputType, EmbeddingModel};
 
@@ -103,3 +105,55 @@ impl VoyageAiEmbedderBuilder {
         })
     }
 }
+
+/// Builds [`VoyageAiEmbedder`]s out of their configuration, see [`ferrochain::config`].
+#[derive(Clone, Default)]
+pub struct VoyageAiEmbedderFactory {
+    http_client: Option<Arc<dyn HttpClient>>,
+}
+
+impl VoyageAiEmbedderFactory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_http_client(mut self, http_client: Arc<dyn HttpClient>) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VoyageAiEmbedderParams {
+    api_key: String,
+    base_url: Option<String>,
+    model: EmbeddingModel,
+    input_type: Option<EmbeddingInputType>,
+    truncation: Option<bool>,
+}
+
+#[ferrochain::async_trait]
+impl ComponentFactory<dyn Embedder> for VoyageAiEmbedderFactory {
+    async fn build(&self, params: Value, _: &Components) -> Result<Box<dyn Embedder>> {
+        let params: VoyageAiEmbedderParams = serde_json::from_value(params)?;
+
+        let mut builder = VoyageAiEmbedder::builder()
+            .with_api_key(params.api_key)
+            .with_model(params.model);
+        if let Some(http_client) = self.http_client.clone() {
+            builder = builder.with_http_client(http_client);
+        }
+        if let Some(base_url) = params.base_url {
+            builder = builder.with_base_url(base_url);
+        }
+        if let Some(input_type) = params.input_type {
+            builder = builder.with_input_type(input_type);
+        }
+        if let Some(truncation) = params.truncation {
+            builder = builder.with_truncation(truncation);
+        }
+
+        Ok(Box::new(builder.build()?))
+    }
+}
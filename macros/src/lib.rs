@@ -0,0 +1,176 @@
+use convert_case::{Case, Casing};
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{
+    parse::Parser, parse_macro_input, punctuated::Punctuated, Expr, ExprLit, FnArg,
+    GenericArgument, ItemFn, Lit, Meta, MetaNameValue, PathArguments, ReturnType, Token, Type,
+};
+
+/// Turns an `async fn(Input) -> Result<Output>` into a `Tool`.
+///
+/// The attribute keeps the function as is and generates a unit struct, named after the
+/// function in pascal case with a `Tool` suffix, implementing `ferrochain::tool::Tool`. The
+/// tool name defaults to the function name and its description to the function doc comments;
+/// both can be overridden with `#[tool(name = "...", description = "...")]`.
+///
+/// ```ignore
+/// /// Returns the current weather in the given city.
+/// #[tool]
+/// async fn get_weather(input: WeatherInput) -> Result<Weather> {
+///     // ...
+/// }
+///
+/// provider.register(GetWeatherTool)?;
+/// ```
+#[proc_macro_attribute]
+pub fn tool(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let function = parse_macro_input!(item as ItemFn);
+
+    match expand(attr.into(), function) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(
+    attr: proc_macro2::TokenStream,
+    function: ItemFn,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let mut name = None;
+    let mut description = None;
+
+    for meta in Punctuated::<Meta, Token![,]>::parse_terminated.parse2(attr)? {
+        let Meta::NameValue(MetaNameValue {
+            path,
+            value:
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(value),
+                    ..
+                }),
+            ..
+        }) = &meta
+        else {
+            return Err(syn::Error::new_spanned(
+                meta,
+                "expected `name = \"...\"` or `description = \"...\"`",
+            ));
+        };
+
+        if path.is_ident("name") {
+            name = Some(value.value());
+        } else if path.is_ident("description") {
+            description = Some(value.value());
+        } else {
+            return Err(syn::Error::new_spanned(path, "unknown tool attribute"));
+        }
+    }
+
+    if function.sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(
+            function.sig.fn_token,
+            "tools must be async functions",
+        ));
+    }
+
+    let input = match function.sig.inputs.iter().collect::<Vec<_>>().as_slice() {
+        [FnArg::Typed(input)] => input.ty.clone(),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &function.sig.inputs,
+                "tools must take exactly one input argument",
+            ))
+        }
+    };
+
+    let output = result_ok_type(&function.sig.output).ok_or_else(|| {
+        syn::Error::new_spanned(&function.sig.output, "tools must return a `Result<Output>`")
+    })?;
+
+    let ident = &function.sig.ident;
+    let vis = &function.vis;
+    let tool = format_ident!("{}Tool", ident.to_string().to_case(Case::Pascal));
+    let name = name.unwrap_or_else(|| ident.to_string());
+    let description = match description {
+        Some(description) => description,
+        None => doc_comment(&function).ok_or_else(|| {
+            syn::Error::new(
+                Span::call_site(),
+                "tools need a doc comment or a `description = \"...\"` attribute",
+            )
+        })?,
+    };
+
+    Ok(quote! {
+        #function
+
+        #[doc = concat!("Tool generated from [`", stringify!(#ident), "`].")]
+        #[derive(Clone, Copy, Debug, Default)]
+        #vis struct #tool;
+
+        #[::ferrochain::async_trait]
+        impl ::ferrochain::tool::Tool for #tool {
+            type Input = #input;
+            type Output = #output;
+
+            fn name(&self) -> String {
+                #name.into()
+            }
+
+            fn description(&self) -> String {
+                #description.into()
+            }
+
+            async fn execute(
+                &self,
+                input: ::ferrochain::serde_json::Value,
+            ) -> ::ferrochain::anyhow::Result<String> {
+                ::ferrochain::tool::execute_typed(input, #ident).await
+            }
+        }
+    })
+}
+
+fn result_ok_type(output: &ReturnType) -> Option<Type> {
+    let ReturnType::Type(_, ty) = output else {
+        return None;
+    };
+    let Type::Path(path) = ty.as_ref() else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Result" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+
+    match arguments.args.first()? {
+        GenericArgument::Type(ty) => Some(ty.clone()),
+        _ => None,
+    }
+}
+
+fn doc_comment(function: &ItemFn) -> Option<String> {
+    let lines = function
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(MetaNameValue {
+                value:
+                    Expr::Lit(ExprLit {
+                        lit: Lit::Str(line),
+                        ..
+                    }),
+                ..
+            }) => Some(line.value()),
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').map(str::to_string).unwrap_or(line))
+        .collect::<Vec<_>>();
+
+    let description = lines.join("\n").trim().to_string();
+    (!description.is_empty()).then_some(description)
+}
@@ -0,0 +1,163 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use ferrochain::{
+    message::{Content, ImageSource},
+    tool::{DynamicTool, ToolDescriptor, ToolProvider},
+};
+use rmcp::{
+    model::{CallToolRequestParam, JsonObject, RawContent, ResourceContents},
+    service::RunningService,
+    transport::{StreamableHttpClientTransport, TokioChildProcess},
+    RoleClient, ServiceExt,
+};
+use schemars::schema::RootSchema;
+use serde_json::Value;
+use tokio::process::Command;
+
+type Service = Arc<RunningService<RoleClient, ()>>;
+
+/// A connection to an MCP server, whose tools can be registered into a [`ToolProvider`].
+///
+/// The connection is closed once the client and all the tools listed from it are dropped.
+#[derive(Clone)]
+pub struct McpClient {
+    service: Service,
+}
+
+impl McpClient {
+    /// Spawns the server as a subprocess and talks to it over its standard input and output.
+    pub async fn stdio(command: Command) -> Result<Self> {
+        let transport = TokioChildProcess::new(command).context("failed to spawn MCP server")?;
+
+        Ok(Self {
+            service: Arc::new(().serve(transport).await?),
+        })
+    }
+
+    /// Connects to a server over the streamable HTTP transport.
+    pub async fn http<U>(url: U) -> Result<Self>
+    where
+        U: Into<Arc<str>>,
+    {
+        let transport = StreamableHttpClientTransport::from_uri(url);
+
+        Ok(Self {
+            service: Arc::new(().serve(transport).await?),
+        })
+    }
+
+    pub async fn tools(&self) -> Result<Vec<McpTool>> {
+        self.service
+            .list_all_tools()
+            .await?
+            .into_iter()
+            .map(|tool| {
+                Ok(McpTool {
+                    descriptor: ToolDescriptor {
+                        description: tool.description.unwrap_or_default().into_owned(),
+                        input: schema(&tool.input_schema)
+                            .with_context(|| format!("invalid input schema for `{}`", tool.name))?,
+                        output: match &tool.output_schema {
+                            Some(output) => schema(output).with_context(|| {
+                                format!("invalid output schema for `{}`", tool.name)
+                            })?,
+                            None => RootSchema::default(),
+                        },
+                        name: tool.name.into_owned(),
+                        external: false,
+                    },
+                    service: self.service.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Lists the server's tools and registers each of them into `provider`.
+    pub async fn register_tools(&self, provider: &mut ToolProvider) -> Result<()> {
+        for tool in self.tools().await? {
+            provider.register_dynamic(tool)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A tool exposed by an MCP server, forwarding its calls to the server.
+pub struct McpTool {
+    descriptor: ToolDescriptor,
+    service: Service,
+}
+
+#[async_trait]
+impl DynamicTool for McpTool {
+    fn schema(&self) -> ToolDescriptor {
+        self.descriptor.clone()
+    }
+
+    async fn execute(&self, input: Value) -> Result<Vec<Content>> {
+        let arguments = match input {
+            Value::Object(arguments) => Some(arguments),
+            Value::Null => None,
+            _ => bail!("tool input must be a JSON object"),
+        };
+
+        let result = self
+            .service
+            .call_tool(CallToolRequestParam {
+                name: self.descriptor.name.clone().into(),
+                arguments,
+            })
+            .await?;
+
+        let mut content = result
+            .content
+            .into_iter()
+            .map(|content| mcp_content_to_ferrochain(content.raw))
+            .collect::<Result<Vec<_>>>()?;
+
+        if content.is_empty() {
+            if let Some(structured) = result.structured_content {
+                content.push(structured.to_string().into());
+            }
+        }
+
+        if result.is_error.unwrap_or(false) {
+            let message = content
+                .iter()
+                .filter_map(|content| match content {
+                    Content::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            return Err(anyhow!(message));
+        }
+
+        Ok(content)
+    }
+}
+
+fn schema(schema: &JsonObject) -> Result<RootSchema> {
+    Ok(serde_json::from_value(Value::Object(schema.clone()))?)
+}
+
+fn mcp_content_to_ferrochain(content: RawContent) -> Result<Content> {
+    Ok(match content {
+        RawContent::Text(text) => text.text.into(),
+        RawContent::Image(image) => Content::Image {
+            source: ImageSource::Base64 {
+                data: image.data,
+                media_type: Some(image.mime_type),
+            },
+        },
+        RawContent::Resource(resource) => match resource.resource {
+            ResourceContents::TextResourceContents { text, .. } => text.into(),
+            ResourceContents::BlobResourceContents { uri, .. } => uri.into(),
+        },
+        RawContent::ResourceLink(resource) => resource.uri.into(),
+        RawContent::Audio(_) => bail!("audio content is not supported"),
+    })
+}
@@ -0,0 +1,5 @@
+mod client;
+mod server;
+
+pub use client::{McpClient, McpTool};
+pub use server::McpServer;
@@ -0,0 +1,152 @@
+//! Publishes the tools described by a configuration file over MCP.
+//!
+//! ```text
+//! ferrochain-mcp <config.yaml> [--http <address>]
+//! ```
+//!
+//! The server speaks over stdio unless an HTTP address is given.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use ferrochain::{
+    config::{Config, Registry},
+    futures::{future::BoxFuture, AsyncReadExt},
+};
+use ferrochain_mcp::McpServer;
+use http_client::{AsyncBody, HttpClient, Request, Response};
+
+const USAGE: &str = "usage: ferrochain-mcp <config> [--http <address>]";
+
+/// An [`HttpClient`] sending requests with reqwest.
+///
+/// Response bodies are read whole before being handed over, so streamed responses arrive at
+/// once.
+struct ReqwestHttpClient(reqwest::Client);
+
+impl HttpClient for ReqwestHttpClient {
+    fn send(&self, request: Request<AsyncBody>) -> BoxFuture<'static, Result<Response<AsyncBody>>> {
+        let client = self.0.clone();
+        Box::pin(async move {
+            let (parts, mut body) = request.into_parts();
+            let mut bytes = vec![];
+            body.read_to_end(&mut bytes).await?;
+
+            let response = client
+                .request(parts.method, parts.uri.to_string())
+                .headers(parts.headers)
+                .body(bytes)
+                .send()
+                .await?;
+
+            let mut builder = Response::builder().status(response.status());
+            for (name, value) in response.headers() {
+                builder = builder.header(name, value);
+            }
+            let body = response.bytes().await?;
+            Ok(builder.body(AsyncBody::from(body.to_vec()))?)
+        })
+    }
+}
+
+/// Creates the registry of every supported component, sharing `http_client` among those
+/// reaching an HTTP API.
+fn registry(http_client: Arc<dyn HttpClient>) -> Registry {
+    let mut registry = Registry::new();
+    registry
+        .register_embedder(
+            "jina",
+            ferrochain_jina_embedder::JinaEmbedderFactory::new()
+                .with_http_client(http_client.clone()),
+        )
+        .register_embedder(
+            "voyageai",
+            ferrochain_voyageai_embedder::VoyageAiEmbedderFactory::new()
+                .with_http_client(http_client.clone()),
+        )
+        .register_reranker(
+            "jina",
+            ferrochain_jina_reranker::JinaRerankerFactory::new()
+                .with_http_client(http_client.clone()),
+        )
+        .register_reranker(
+            "voyageai",
+            ferrochain_voyageai_reranker::VoyageAiRerankerFactory::new()
+                .with_http_client(http_client.clone()),
+        )
+        .register_vector_store(
+            "qdrant",
+            ferrochain_qdrant_vectorstore::QdrantVectorStoreFactory,
+        )
+        .register_vector_store(
+            "surrealdb",
+            ferrochain_surrealdb_vectorstore::SurrealVectorStoreFactory,
+        )
+        .register_retriever(
+            "exa",
+            ferrochain_exa_retriever::ExaRetrieverFactory::new()
+                .with_http_client(http_client.clone()),
+        )
+        .register_retriever(
+            "firecrawl",
+            ferrochain_firecrawl_retriever::FirecrawlRetrieverFactory,
+        )
+        .register_retriever(
+            "tavily",
+            ferrochain_tavily_retriever::TavilyRetrieverFactory,
+        )
+        .register_completion(
+            "anthropic",
+            ferrochain_anthropic_completion::AnthropicCompletionFactory::new()
+                .with_http_client(http_client),
+        );
+    registry
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let config = args.next().ok_or_else(|| anyhow!(USAGE))?;
+    let http = match (args.next().as_deref(), args.next()) {
+        (None, _) => None,
+        (Some("--http"), Some(address)) => Some(address),
+        _ => bail!(USAGE),
+    };
+
+    let http_client = Arc::new(ReqwestHttpClient(reqwest::Client::new()));
+    let components = registry(http_client)
+        .build(&Config::from_path(config)?)
+        .await?;
+    let server = McpServer::new(components.tool_provider()?);
+
+    match http {
+        Some(address) => server.serve_http(address).await,
+        None => server.serve_stdio().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_build_completion() {
+        let config = Config::from_json_str(
+            r#"{
+                "completions": {
+                    "default": {
+                        "kind": "anthropic",
+                        "api_key": "key",
+                        "model": "claude-3-5-sonnet-20241022",
+                        "max_tokens": 1024
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let http_client = Arc::new(ReqwestHttpClient(reqwest::Client::new()));
+        let components = registry(http_client).build(&config).await.unwrap();
+        assert!(components.completion("default").is_ok());
+    }
+}
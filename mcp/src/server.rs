@@ -0,0 +1,218 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use anyhow::Result;
+use ferrochain::{
+    message::{Content, DocumentSource, ImageSource, ToolUse},
+    tool::ToolProvider,
+};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+    service::TowerToHyperService,
+};
+use rmcp::{
+    model::{
+        CallToolRequestParam, CallToolResult, Implementation, JsonObject, ListToolsResult,
+        PaginatedRequestParam, ServerCapabilities, ServerInfo, Tool,
+    },
+    service::RequestContext,
+    transport::{
+        stdio,
+        streamable_http_server::{session::local::LocalSessionManager, StreamableHttpService},
+    },
+    ErrorData, RoleServer, ServerHandler, ServiceExt,
+};
+use schemars::schema::RootSchema;
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, ToSocketAddrs};
+
+/// An MCP server publishing the tools of a [`ToolProvider`].
+///
+/// `tools/list` is answered with [`ToolProvider::list`], while `tools/call` is dispatched
+/// through [`ToolProvider::execute`], so inputs are validated and failures are reported as
+/// error results. External tools are not published, as only the application can run them, nor
+/// are the tools whose input isn't an object, as MCP requires.
+#[derive(Clone)]
+pub struct McpServer {
+    provider: Arc<ToolProvider>,
+    calls: Arc<AtomicU64>,
+}
+
+impl McpServer {
+    pub fn new(provider: ToolProvider) -> Self {
+        Self {
+            provider: Arc::new(provider),
+            calls: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Serves a single client over the standard input and output, until it disconnects.
+    pub async fn serve_stdio(self) -> Result<()> {
+        self.serve(stdio()).await?.waiting().await?;
+        Ok(())
+    }
+
+    /// Serves clients over the streamable HTTP transport.
+    pub async fn serve_http<A>(self, addr: A) -> Result<()>
+    where
+        A: ToSocketAddrs,
+    {
+        let listener = TcpListener::bind(addr).await?;
+        let service = TowerToHyperService::new(StreamableHttpService::new(
+            move || Ok(self.clone()),
+            LocalSessionManager::default().into(),
+            Default::default(),
+        ));
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let service = service.clone();
+            tokio::spawn(async move {
+                let _ = Builder::new(TokioExecutor::new())
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await;
+            });
+        }
+    }
+}
+
+impl ServerHandler for McpServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            server_info: Implementation {
+                name: env!("CARGO_PKG_NAME").into(),
+                version: env!("CARGO_PKG_VERSION").into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _: Option<PaginatedRequestParam>,
+        _: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        let tools = self
+            .provider
+            .list()
+            .filter(|tool| !tool.external)
+            .filter_map(|tool| match mcp_input_schema(&tool.input) {
+                Some(input) => Some(Tool::new(tool.name, tool.description, Arc::new(input))),
+                None => {
+                    tracing::warn!("not publishing `{}`, its input isn't an object", tool.name);
+                    None
+                }
+            })
+            .collect();
+
+        Ok(ListToolsResult::with_all_items(tools))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let tool_use = ToolUse {
+            id: format!("mcp_{}", self.calls.fetch_add(1, Ordering::Relaxed)),
+            tool: request.name.into_owned(),
+            input: Value::Object(request.arguments.unwrap_or_default()),
+        };
+
+        let result = self
+            .provider
+            .execute(&tool_use)
+            .await
+            .map_err(|error| ErrorData::internal_error(format!("{error:#}"), None))?;
+
+        let content = result
+            .content
+            .into_iter()
+            .filter_map(ferrochain_content_to_mcp)
+            .collect();
+
+        Ok(if result.is_error {
+            CallToolResult::error(content)
+        } else {
+            CallToolResult::success(content)
+        })
+    }
+}
+
+/// Turns the input schema of a tool into the object schema MCP expects, or `None` if the
+/// input isn't an object. Tools without input, whose schema is `null`, take an empty object.
+fn mcp_input_schema(input: &RootSchema) -> Option<JsonObject> {
+    let Ok(Value::Object(mut input)) = serde_json::to_value(input) else {
+        return None;
+    };
+
+    match input.get("type") {
+        Some(Value::String(kind)) if kind == "object" => {}
+        Some(Value::String(kind)) if kind == "null" => {
+            return match json!({ "type": "object", "properties": {} }) {
+                Value::Object(input) => Some(input),
+                _ => None,
+            };
+        }
+        Some(_) => return None,
+        // Schemas without a type, such as the one of any JSON value, are given an object one.
+        None => {
+            input.insert("type".into(), "object".into());
+        }
+    }
+
+    Some(input)
+}
+
+fn ferrochain_content_to_mcp(content: Content) -> Option<rmcp::model::Content> {
+    match content {
+        Content::Text { text } => Some(rmcp::model::Content::text(text)),
+        Content::Image { source } => {
+            // PNG is the most common format of tool outputs, when the media type is unknown.
+            let media_type = source.media_type().unwrap_or_else(|| "image/png".into());
+            Some(match source {
+                ImageSource::Base64 { data, .. } => rmcp::model::Content::image(data, media_type),
+                ImageSource::Url { url, .. } => rmcp::model::Content::text(url),
+            })
+        }
+        Content::Document {
+            source: DocumentSource::Text { text },
+            ..
+        } => Some(rmcp::model::Content::text(text)),
+        // MCP embeds binary files as resources, which need a URI a PDF document doesn't have.
+        Content::Document {
+            source: DocumentSource::Pdf { .. },
+            ..
+        } => None,
+        Content::Thinking { .. }
+        | Content::RedactedThinking { .. }
+        | Content::ToolUse(_)
+        | Content::ToolResult(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use schemars::schema_for;
+
+    use super::*;
+
+    #[test]
+    fn test_mcp_input_schema() {
+        assert_eq!(
+            Value::Object(mcp_input_schema(&schema_for!(())).unwrap()),
+            json!({ "type": "object", "properties": {} })
+        );
+        assert!(mcp_input_schema(&schema_for!(String)).is_none());
+        assert!(mcp_input_schema(&schema_for!(Vec<i64>)).is_none());
+        assert_eq!(
+            mcp_input_schema(&schema_for!(Value)).unwrap()["type"],
+            "object"
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! A minimal MCP server speaking over stdio, used by the integration tests.
+
+use std::sync::Arc;
+
+use rmcp::{
+    model::{
+        CallToolRequestParam, CallToolResult, Content, ListToolsResult, PaginatedRequestParam,
+        ServerCapabilities, ServerInfo, Tool,
+    },
+    service::RequestContext,
+    transport::stdio,
+    ErrorData, RoleServer, ServerHandler, ServiceExt,
+};
+use serde_json::json;
+
+struct EchoServer;
+
+impl ServerHandler for EchoServer {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..Default::default()
+        }
+    }
+
+    async fn list_tools(
+        &self,
+        _: Option<PaginatedRequestParam>,
+        _: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        let schema = json!({
+            "type": "object",
+            "properties": { "text": { "type": "string" } },
+            "required": ["text"],
+        });
+
+        Ok(ListToolsResult::with_all_items(vec![
+            Tool::new(
+                "echo",
+                "Echoes the given text",
+                Arc::new(schema.as_object().unwrap().clone()),
+            ),
+            Tool::new(
+                "fail",
+                "Always fails",
+                Arc::new(json!({ "type": "object" }).as_object().unwrap().clone()),
+            ),
+        ]))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        _: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        match request.name.as_ref() {
+            "echo" => {
+                let text = request
+                    .arguments
+                    .and_then(|arguments| arguments.get("text").cloned())
+                    .and_then(|text| text.as_str().map(String::from))
+                    .unwrap_or_default();
+
+                Ok(CallToolResult::success(vec![Content::text(text)]))
+            }
+            "fail" => Ok(CallToolResult::error(vec![Content::text("it broke")])),
+            name => Err(ErrorData::invalid_params(
+                format!("unknown tool `{name}`"),
+                None,
+            )),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    EchoServer.serve(stdio()).await?.waiting().await?;
+
+    Ok(())
+}
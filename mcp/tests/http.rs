@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use ferrochain::{
+    message::ToolUse,
+    tool::{ExternalTool, FnTool, ToolProvider},
+};
+use ferrochain_mcp::{McpClient, McpServer};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Deserialize, JsonSchema)]
+struct AddInput {
+    a: i64,
+    b: i64,
+}
+
+#[tokio::test]
+async fn test_http_server_publishes_tool_provider() {
+    let mut provider = ToolProvider::new();
+    provider
+        .register(FnTool::new(
+            "add",
+            "Adds two numbers",
+            |input: AddInput| async move {
+                input
+                    .a
+                    .checked_add(input.b)
+                    .ok_or_else(|| anyhow!("overflow"))
+            },
+        ))
+        .unwrap();
+    provider
+        .register(FnTool::new("now", "Tells the time", |_: ()| async {
+            Ok("noon".to_string())
+        }))
+        .unwrap();
+    provider
+        .register(ExternalTool::<Value, String>::new(
+            "ask_user",
+            "Asks the user",
+        ))
+        .unwrap();
+
+    let address = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    tokio::spawn(McpServer::new(provider).serve_http(address));
+
+    let mut client = None;
+    for _ in 0..50 {
+        match McpClient::http(format!("http://{address}/mcp")).await {
+            Ok(connected) => {
+                client = Some(connected);
+                break;
+            }
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    }
+    let client = client.expect("server did not start");
+
+    let mut remote = ToolProvider::new();
+    client.register_tools(&mut remote).await.unwrap();
+
+    let tools = remote.list().map(|tool| tool.name).collect::<Vec<_>>();
+    assert_eq!(tools, ["add", "now"]);
+
+    // Tools without input are published with an empty object schema.
+    let now = remote.list().find(|tool| tool.name == "now").unwrap();
+    assert_eq!(serde_json::to_value(now.input).unwrap()["type"], "object");
+
+    let result = remote
+        .execute(&ToolUse {
+            id: "1".into(),
+            tool: "add".into(),
+            input: json!({ "a": 1, "b": 2 }),
+        })
+        .await
+        .unwrap();
+    assert!(!result.is_error);
+    assert_eq!(result.text(), "3");
+
+    let result = remote
+        .execute(&ToolUse {
+            id: "2".into(),
+            tool: "add".into(),
+            input: json!({ "a": i64::MAX, "b": 1 }),
+        })
+        .await
+        .unwrap();
+    assert!(result.is_error);
+    assert!(result.text().contains("overflow"));
+}
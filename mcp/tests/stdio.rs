@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use ferrochain::{message::ToolUse, tool::ToolProvider};
+use ferrochain_mcp::McpClient;
+use serde_json::json;
+use tokio::process::Command;
+
+fn echo_server() -> PathBuf {
+    // Examples are built alongside the integration tests, in the parent of `deps/`.
+    let mut path = std::env::current_exe().unwrap();
+    path.pop();
+    path.pop();
+    path.push("examples");
+    path.push(format!("echo_server{}", std::env::consts::EXE_SUFFIX));
+    path
+}
+
+#[tokio::test]
+async fn test_stdio_tools_are_registered_and_executed() {
+    let client = McpClient::stdio(Command::new(echo_server())).await.unwrap();
+
+    let mut provider = ToolProvider::new();
+    client.register_tools(&mut provider).await.unwrap();
+
+    let mut names = provider.list().map(|tool| tool.name).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["echo", "fail"]);
+
+    let echo = provider.list().find(|tool| tool.name == "echo").unwrap();
+    assert_eq!(echo.description, "Echoes the given text");
+
+    let result = provider
+        .execute(&ToolUse {
+            id: "1".into(),
+            tool: "echo".into(),
+            input: json!({ "text": "hello" }),
+        })
+        .await
+        .unwrap();
+    assert!(!result.is_error);
+    assert_eq!(result.text(), "hello");
+
+    let result = provider
+        .execute(&ToolUse {
+            id: "2".into(),
+            tool: "echo".into(),
+            input: json!({}),
+        })
+        .await
+        .unwrap();
+    assert!(result.is_error);
+
+    let result = provider
+        .execute(&ToolUse {
+            id: "3".into(),
+            tool: "fail".into(),
+            input: json!({}),
+        })
+        .await
+        .unwrap();
+    assert!(result.is_error);
+    assert!(result.text().contains("it broke"));
+}
@@ -0,0 +1,492 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use ferrochain::{
+    futures::lock::Mutex,
+    memory::Memory,
+    message::{Message, MESSAGE_VERSION},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+const DEFAULT_SESSION: &str = "default";
+
+/// A [`Memory`] appending the messages of conversations to a JSON Lines file.
+///
+/// Each memory is bound to a session, so that a single file can hold many conversations. Every
+/// call to [`Memory::add_messages`] or [`Memory::clear`] appends a single line, synced to disk
+/// before returning. A line torn by a crash is discarded when the file is opened, so a batch of
+/// messages is either fully stored or not at all.
+///
+/// The file is read once, when the memory is built, and grows until [`JsonlMemory::compact`]
+/// rewrites it.
+#[derive(Clone)]
+pub struct JsonlMemory {
+    state: Arc<Mutex<State>>,
+    session: String,
+}
+
+pub struct JsonlMemoryBuilder {
+    path: Option<PathBuf>,
+    session: Option<String>,
+}
+
+struct State {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    sessions: HashMap<String, Session>,
+    entries: usize,
+}
+
+#[derive(Default)]
+struct Session {
+    messages: Vec<Message>,
+    last_entry: usize,
+}
+
+/// A line of the file.
+#[derive(Deserialize, Serialize)]
+struct Entry {
+    /// The [`MESSAGE_VERSION`] the messages were written with.
+    version: u32,
+    session: String,
+    #[serde(flatten)]
+    event: Event,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event {
+    Messages {
+        messages: Vec<Value>,
+    },
+    Clear,
+    /// Replaces the messages of the session, in a single line so that a torn write leaves the
+    /// previous messages in place.
+    Replace {
+        messages: Vec<Value>,
+    },
+}
+
+impl JsonlMemory {
+    pub fn builder() -> JsonlMemoryBuilder {
+        JsonlMemoryBuilder {
+            path: None,
+            session: None,
+        }
+    }
+
+    /// Returns a memory over another session of the same file.
+    pub fn session<S>(&self, session: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            state: self.state.clone(),
+            session: session.into(),
+        }
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session
+    }
+
+    /// Lists the sessions holding messages, most recently active first.
+    pub async fn sessions(&self) -> Vec<String> {
+        let state = self.state.lock().await;
+        let mut sessions = state
+            .sessions
+            .iter()
+            .filter(|(_, session)| !session.messages.is_empty())
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|(_, session)| std::cmp::Reverse(session.last_entry));
+        sessions.into_iter().map(|(id, _)| id.clone()).collect()
+    }
+
+    /// Rewrites the file with only the messages currently stored, in the latest message
+    /// version.
+    ///
+    /// The new file is written next to the current one and renamed over it, so a crash leaves
+    /// either file intact. The directory is synced once the file is renamed, so that the lines
+    /// appended afterwards can't be lost along with the rename.
+    pub async fn compact(&self) -> Result<()> {
+        let mut state = self.state.lock().await;
+
+        state
+            .sessions
+            .retain(|_, session| !session.messages.is_empty());
+        let mut order = state.sessions.keys().cloned().collect::<Vec<_>>();
+        order.sort_by_key(|id| state.sessions[id].last_entry);
+
+        let mut content = String::new();
+        for id in &order {
+            content.push_str(&encode(id, &state.sessions[id].messages)?);
+        }
+
+        let mut tmp_path = state.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let written = async {
+            let mut tmp = File::create(&tmp_path).await?;
+            tmp.write_all(content.as_bytes()).await?;
+            tmp.sync_all().await?;
+            drop(tmp);
+            tokio::fs::rename(&tmp_path, &state.path).await
+        }
+        .await;
+        if let Err(error) = written {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+            return Err(error.into());
+        }
+
+        // The current file was replaced, so lines must now be appended to the new one.
+        state.file = OpenOptions::new().append(true).open(&state.path).await?;
+        state.len = content.len() as u64;
+        state.entries = order.len();
+        for (index, id) in order.iter().enumerate() {
+            if let Some(session) = state.sessions.get_mut(id) {
+                session.last_entry = index + 1;
+            }
+        }
+
+        sync_parent(&state.path).await
+    }
+
+    async fn append(&self, line: String, event: impl FnOnce(&mut Session)) -> Result<()> {
+        let mut state = self.state.lock().await;
+
+        let written = async {
+            state.file.write_all(line.as_bytes()).await?;
+            state.file.flush().await?;
+            state.file.sync_data().await
+        }
+        .await;
+        if let Err(error) = written {
+            // Drop whatever part of the line was written, so the next line starts cleanly.
+            let len = state.len;
+            state.file.set_len(len).await?;
+            return Err(error.into());
+        }
+
+        state.len += line.len() as u64;
+        state.entries += 1;
+        let entries = state.entries;
+        let session = state.sessions.entry(self.session.clone()).or_default();
+        event(session);
+        session.last_entry = entries;
+        Ok(())
+    }
+}
+
+/// Syncs the directory of `path`, so that the entry of a file renamed into it is durable.
+#[cfg(unix)]
+async fn sync_parent(path: &Path) -> Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(parent).await?.sync_all().await?;
+    Ok(())
+}
+
+/// Directories can't be opened to be synced on other platforms.
+#[cfg(not(unix))]
+async fn sync_parent(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+impl JsonlMemoryBuilder {
+    /// Opens, or creates, the file at `path`.
+    pub fn with_path<P>(mut self, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Sets the session of the memory, `default` if not set.
+    pub fn with_session<S>(mut self, session: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.session = Some(session.into());
+        self
+    }
+
+    /// Opens the file and loads the stored messages, upgrading those written with an older
+    /// message version.
+    pub async fn build(self) -> Result<JsonlMemory> {
+        let path = self.path.ok_or_else(|| anyhow!("path is required"))?;
+
+        let content = match tokio::fs::read(&path).await {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(error) => return Err(error.into()),
+        };
+
+        // Only complete lines count, anything after the last newline is a torn write.
+        let len = content
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map_or(0, |end| end + 1);
+
+        let mut sessions = HashMap::<String, Session>::new();
+        let mut entries = 0;
+        for (index, line) in content[..len].split(|byte| *byte == b'\n').enumerate() {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let entry: Entry = serde_json::from_slice(line).map_err(|error| {
+                anyhow!(
+                    "invalid entry at {}:{}: {}",
+                    path.display(),
+                    index + 1,
+                    error
+                )
+            })?;
+
+            entries += 1;
+            let session = sessions.entry(entry.session).or_default();
+            session.last_entry = entries;
+            let messages = match entry.event {
+                Event::Messages { messages } => messages,
+                Event::Clear => {
+                    session.messages.clear();
+                    vec![]
+                }
+                Event::Replace { messages } => {
+                    session.messages.clear();
+                    messages
+                }
+            };
+            for message in messages {
+                session
+                    .messages
+                    .push(Message::from_stored(entry.version, message)?);
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        if len < content.len() {
+            file.set_len(len as u64).await?;
+            file.sync_data().await?;
+        }
+
+        Ok(JsonlMemory {
+            state: Arc::new(Mutex::new(State {
+                path,
+                file,
+                len: len as u64,
+                sessions,
+                entries,
+            })),
+            session: self.session.unwrap_or_else(|| DEFAULT_SESSION.into()),
+        })
+    }
+}
+
+fn encode(session: &str, messages: &[Message]) -> Result<String> {
+    let entry = Entry {
+        version: MESSAGE_VERSION,
+        session: session.into(),
+        event: Event::Messages {
+            messages: messages
+                .iter()
+                .map(serde_json::to_value)
+                .collect::<Result<_, _>>()?,
+        },
+    };
+    Ok(serde_json::to_string(&entry)? + "\n")
+}
+
+#[ferrochain::async_trait]
+impl Memory for JsonlMemory {
+    /// Appends the messages as a single line.
+    ///
+    /// Messages without a creation time are stamped with the current time.
+    async fn add_messages(&self, mut messages: Vec<Message>) -> Result<()> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        for message in &mut messages {
+            message.created_at.get_or_insert(now);
+        }
+
+        let line = encode(&self.session, &messages)?;
+        self.append(line, |session| session.messages.extend(messages))
+            .await
+    }
+
+    async fn messages(&self) -> Result<Vec<Message>> {
+        let state = self.state.lock().await;
+        Ok(state
+            .sessions
+            .get(&self.session)
+            .map(|session| session.messages.clone())
+            .unwrap_or_default())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let entry = Entry {
+            version: MESSAGE_VERSION,
+            session: self.session.clone(),
+            event: Event::Clear,
+        };
+        let line = serde_json::to_string(&entry)? + "\n";
+        self.append(line, |session| session.messages.clear()).await
+    }
+
+    /// Appends the messages as a single line replacing those of the session.
+    ///
+    /// Messages without a creation time are stamped with the current time.
+    async fn replace_messages(&self, mut messages: Vec<Message>) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        for message in &mut messages {
+            message.created_at.get_or_insert(now);
+        }
+
+        let entry = Entry {
+            version: MESSAGE_VERSION,
+            session: self.session.clone(),
+            event: Event::Replace {
+                messages: messages
+                    .iter()
+                    .map(serde_json::to_value)
+                    .collect::<Result<_, _>>()?,
+            },
+        };
+        let line = serde_json::to_string(&entry)? + "\n";
+        self.append(line, |session| session.messages = messages)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use ferrochain::message::{Content, Role};
+
+    use super::*;
+
+    fn message(text: &str) -> Message {
+        Message {
+            role: Role::User,
+            content: vec![text.into()],
+            ..Default::default()
+        }
+    }
+
+    fn texts(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|message| match &message.content[0] {
+                Content::Text { text } => text.clone(),
+                _ => panic!("expected a text message"),
+            })
+            .collect()
+    }
+
+    async fn open(path: &std::path::Path) -> JsonlMemory {
+        JsonlMemory::builder()
+            .with_path(path)
+            .with_session("a")
+            .build()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_memory_persists_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memory.jsonl");
+
+        let a = open(&path).await;
+        let b = a.session("b");
+        a.add_messages(vec![message("1"), message("2")])
+            .await
+            .unwrap();
+        b.add_messages(vec![message("hello")]).await.unwrap();
+        b.clear().await.unwrap();
+        b.add_messages(vec![message("again")]).await.unwrap();
+
+        b.add_messages(vec![message("to be replaced")])
+            .await
+            .unwrap();
+        b.replace_messages(vec![message("again")]).await.unwrap();
+
+        let a = open(&path).await;
+        let b = a.session("b");
+        assert_eq!(texts(&a.messages().await.unwrap()), ["1", "2"]);
+        assert_eq!(texts(&b.messages().await.unwrap()), ["again"]);
+        assert_eq!(a.sessions().await, ["b", "a"]);
+
+        a.compact().await.unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert!(!content.contains("hello"));
+
+        a.add_messages(vec![message("3")]).await.unwrap();
+        let a = open(&path).await;
+        assert_eq!(texts(&a.messages().await.unwrap()), ["1", "2", "3"]);
+        assert_eq!(a.sessions().await, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_memory_discards_torn_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memory.jsonl");
+
+        let memory = open(&path).await;
+        memory.add_messages(vec![message("1")]).await.unwrap();
+        drop(memory);
+
+        // A crash in the middle of a write leaves an incomplete line behind.
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(br#"{"version":1,"session":"a","type":"mess"#)
+            .unwrap();
+        drop(file);
+
+        let memory = open(&path).await;
+        memory.add_messages(vec![message("2")]).await.unwrap();
+
+        let memory = open(&path).await;
+        assert_eq!(texts(&memory.messages().await.unwrap()), ["1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_memory_rejects_unknown_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memory.jsonl");
+        std::fs::write(
+            &path,
+            "{\"version\":99,\"session\":\"a\",\"type\":\"messages\",\"messages\":[{}]}\n",
+        )
+        .unwrap();
+
+        assert!(JsonlMemory::builder()
+            .with_path(&path)
+            .build()
+            .await
+            .is_err());
+    }
+}
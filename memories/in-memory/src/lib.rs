@@ -32,4 +32,10 @@ impl Memory for InMemoryMemory {
         inner.clear();
         Ok(())
     }
+
+    async fn replace_messages(&self, messages: Vec<Message>) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        *inner = messages;
+        Ok(())
+    }
 }
@@ -0,0 +1,309 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Result};
+use ferrochain::{
+    memory::Memory,
+    message::{Message, MESSAGE_VERSION},
+    serde_json,
+};
+use rusqlite::{params, Connection, Transaction};
+
+const DEFAULT_SESSION: &str = "default";
+
+/// The migrations of the database schema, applied in order. The schema version is stored in
+/// the `user_version` pragma, so only new migrations run when a database is opened.
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE messages (
+        seq INTEGER PRIMARY KEY AUTOINCREMENT,
+        session TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        version INTEGER NOT NULL,
+        message TEXT NOT NULL
+    );
+    CREATE INDEX messages_session ON messages (session, seq);
+"];
+
+/// A [`Memory`] storing the messages of conversations in a SQLite database.
+///
+/// Each memory is bound to a session, so that a single database can hold many conversations.
+/// The database uses write-ahead logging with full synchronization, and each batch of messages
+/// is inserted in a single transaction, so that a crash never leaves a partial batch behind.
+#[derive(Clone)]
+pub struct SqliteMemory {
+    connection: Arc<Mutex<Connection>>,
+    session: String,
+}
+
+pub struct SqliteMemoryBuilder {
+    path: Option<PathBuf>,
+    connection: Option<Connection>,
+    session: Option<String>,
+}
+
+impl SqliteMemory {
+    pub fn builder() -> SqliteMemoryBuilder {
+        SqliteMemoryBuilder {
+            path: None,
+            connection: None,
+            session: None,
+        }
+    }
+
+    /// Returns a memory over another session of the same database.
+    pub fn session<S>(&self, session: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            connection: self.connection.clone(),
+            session: session.into(),
+        }
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session
+    }
+
+    /// Lists the sessions holding messages, most recently active first.
+    pub async fn sessions(&self) -> Result<Vec<String>> {
+        self.run(|connection, _| {
+            let mut statement = connection
+                .prepare("SELECT session FROM messages GROUP BY session ORDER BY MAX(seq) DESC")?;
+            let sessions = statement
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+            Ok(sessions)
+        })
+        .await
+    }
+
+    /// Runs `f` on a blocking thread, with the connection and the session of the memory.
+    async fn run<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Connection, &str) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.connection.clone();
+        let session = self.session.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection
+                .lock()
+                .map_err(|_| anyhow!("the SQLite connection is poisoned"))?;
+            f(&mut connection, &session)
+        })
+        .await?
+    }
+}
+
+impl SqliteMemoryBuilder {
+    /// Opens, or creates, the database at `path`.
+    pub fn with_path<P>(mut self, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Uses an already open connection, such as an in-memory database.
+    pub fn with_connection(mut self, connection: Connection) -> Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    /// Sets the session of the memory, `default` if not set.
+    pub fn with_session<S>(mut self, session: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.session = Some(session.into());
+        self
+    }
+
+    /// Opens the database and applies the pending migrations.
+    pub fn build(self) -> Result<SqliteMemory> {
+        let mut connection = match (self.connection, self.path) {
+            (Some(connection), _) => connection,
+            (None, Some(path)) => Connection::open(path)?,
+            (None, None) => bail!("path or connection is required"),
+        };
+
+        // In-memory databases answer `memory`, which is fine.
+        connection.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        connection.pragma_update(None, "synchronous", "FULL")?;
+        migrate(&mut connection)?;
+
+        Ok(SqliteMemory {
+            connection: Arc::new(Mutex::new(connection)),
+            session: self.session.unwrap_or_else(|| DEFAULT_SESSION.into()),
+        })
+    }
+}
+
+fn migrate(connection: &mut Connection) -> Result<()> {
+    let transaction = connection.transaction()?;
+    let version: usize = transaction.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        bail!(
+            "the database schema version {} is newer than the latest known, {}",
+            version,
+            MIGRATIONS.len()
+        );
+    }
+
+    for migration in &MIGRATIONS[version..] {
+        transaction.execute_batch(migration)?;
+    }
+    transaction.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    transaction.commit()?;
+    Ok(())
+}
+
+#[ferrochain::async_trait]
+impl Memory for SqliteMemory {
+    /// Stores the messages in a single transaction.
+    ///
+    /// Messages without a creation time are stamped with the current time.
+    async fn add_messages(&self, messages: Vec<Message>) -> Result<()> {
+        self.run(move |connection, session| {
+            let transaction = connection.transaction()?;
+            insert_messages(&transaction, session, messages)?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn messages(&self) -> Result<Vec<Message>> {
+        self.run(|connection, session| {
+            let mut statement = connection.prepare(
+                "SELECT version, message FROM messages WHERE session = ?1 ORDER BY seq ASC",
+            )?;
+            let rows = statement
+                .query_map([session], |row| {
+                    Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            rows.into_iter()
+                .map(|(version, message)| {
+                    Message::from_stored(version, serde_json::from_str(&message)?)
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.run(|connection, session| {
+            connection.execute("DELETE FROM messages WHERE session = ?1", [session])?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Deletes the messages of the session and stores the new ones in a single transaction.
+    async fn replace_messages(&self, messages: Vec<Message>) -> Result<()> {
+        self.run(move |connection, session| {
+            let transaction = connection.transaction()?;
+            transaction.execute("DELETE FROM messages WHERE session = ?1", [session])?;
+            insert_messages(&transaction, session, messages)?;
+            transaction.commit()?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// Stores the messages of the session, stamping those without a creation time with the
+/// current time.
+fn insert_messages(transaction: &Transaction, session: &str, messages: Vec<Message>) -> Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut statement = transaction.prepare(
+        "INSERT INTO messages (session, created_at, version, message) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for mut message in messages {
+        let created_at = *message.created_at.get_or_insert(now);
+        statement.execute(params![
+            session,
+            created_at,
+            MESSAGE_VERSION,
+            serde_json::to_string(&message)?,
+        ])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ferrochain::message::{Content, Role};
+
+    use super::*;
+
+    fn message(text: &str) -> Message {
+        Message {
+            role: Role::User,
+            content: vec![text.into()],
+            ..Default::default()
+        }
+    }
+
+    fn texts(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|message| match &message.content[0] {
+                Content::Text { text } => text.clone(),
+                _ => panic!("expected a text message"),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_memory() {
+        let a = SqliteMemory::builder()
+            .with_connection(Connection::open_in_memory().unwrap())
+            .with_session("a")
+            .build()
+            .unwrap();
+        let b = a.session("b");
+
+        a.add_messages(vec![message("1"), message("2")])
+            .await
+            .unwrap();
+        b.add_messages(vec![message("hello")]).await.unwrap();
+        a.add_messages(vec![message("3")]).await.unwrap();
+
+        let messages = a.messages().await.unwrap();
+        assert_eq!(texts(&messages), ["1", "2", "3"]);
+        assert!(messages.iter().all(|message| message.created_at.is_some()));
+        assert_eq!(texts(&b.messages().await.unwrap()), ["hello"]);
+        assert_eq!(a.sessions().await.unwrap(), ["a", "b"]);
+
+        a.replace_messages(vec![message("summary"), message("3")])
+            .await
+            .unwrap();
+        assert_eq!(texts(&a.messages().await.unwrap()), ["summary", "3"]);
+        assert_eq!(texts(&b.messages().await.unwrap()), ["hello"]);
+
+        a.clear().await.unwrap();
+        assert!(a.messages().await.unwrap().is_empty());
+        assert_eq!(b.sessions().await.unwrap(), ["b"]);
+    }
+
+    #[test]
+    fn test_migrations() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+        // Opening an up-to-date database applies no migration.
+        migrate(&mut connection).unwrap();
+
+        connection
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        assert!(migrate(&mut connection).is_err());
+    }
+}
@@ -1,12 +1,72 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use surrealdb::{engine::any::Any, Surreal};
+use uuid::Uuid;
 
-use ferrochain::{anyhow::Result, memory::Memory, message::Message};
+use ferrochain::{
+    anyhow::Result,
+    memory::Memory,
+    message::{Message, MESSAGE_VERSION},
+    serde_json::{json, Value},
+};
 
+const DEFAULT_SESSION: &str = "default";
+
+/// Creates the `$records` of the session, numbering them after the sequence number `$last`.
+/// Records are keyed on their session and message id, so ids only need to be unique within a
+/// session.
+const CREATE_RECORDS: &str = "
+    FOR $record IN $records {
+        CREATE type::thing($table, [$session, $record.id]) CONTENT {
+            session: $session,
+            seq: $last + $record.offset + 1,
+            created_at: $record.created_at,
+            version: $record.version,
+            message: $record.message,
+        };
+    };
+";
+
+/// A [`Memory`] storing the messages of conversations in a SurrealDB table.
+///
+/// Each memory is bound to a session, so that a single table can hold many conversations.
+/// Messages are stored along with their session and a sequence number, preserving their
+/// insertion order.
+///
+/// [`migrate`](Self::migrate) needs to run once per table, to define its indexes and to move
+/// the messages stored by earlier versions, which had no session, to the `default` session.
+#[derive(Clone)]
 pub struct SurrealDbMemory {
     db: Arc<Surreal<Any>>,
     table: String,
+    session: String,
+}
+
+/// The summary of a conversation stored in a [`SurrealDbMemory`] table.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Session {
+    pub id: String,
+    /// The number of stored messages.
+    pub messages: u64,
+    /// The creation time of the most recent message, in seconds since the Unix epoch.
+    pub last_activity: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct StoredMessage {
+    /// The [`MESSAGE_VERSION`] the message was written with, missing from the records written
+    /// before versions were stored, which are of the first version.
+    version: Option<u32>,
+    message: Value,
+}
+
+impl StoredMessage {
+    fn into_message(self) -> Result<Message> {
+        Message::from_stored(self.version.unwrap_or(1), self.message)
+    }
 }
 
 impl SurrealDbMemory {
@@ -14,13 +74,150 @@ impl SurrealDbMemory {
         SurrealDbMemoryBuilder {
             db: None,
             table: None,
+            session: None,
+        }
+    }
+
+    /// Returns a memory over another session of the same table.
+    pub fn session<S>(&self, session: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            db: self.db.clone(),
+            table: self.table.clone(),
+            session: session.into(),
         }
     }
+
+    pub fn session_id(&self) -> &str {
+        &self.session
+    }
+
+    /// Defines the indexes used to look messages up by session, which also guarantee that
+    /// sequence numbers are unique within a session.
+    async fn ensure_index(&self) -> Result<()> {
+        self.db
+            .query(format!(
+                "DEFINE INDEX IF NOT EXISTS {table}_session_seq ON TABLE {table} FIELDS session, seq UNIQUE",
+                table = self.table
+            ))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    /// Prepares the table for this version, moving the messages stored by earlier versions to
+    /// the end of the `default` session and defining the indexes of the table.
+    ///
+    /// Running it again once the table is migrated has no effect.
+    pub async fn migrate(&self) -> Result<()> {
+        // Legacy messages all lack a sequence number, which the unique index would reject.
+        self.migrate_legacy_messages().await?;
+        self.ensure_index().await
+    }
+
+    /// Moves the messages stored without a session, by versions which stored each message as
+    /// a record of its own, to the end of the default session, in the order they were created.
+    async fn migrate_legacy_messages(&self) -> Result<()> {
+        let mut result = self
+            .db
+            .query("SELECT *, meta::id(id) AS key FROM type::table($table) WHERE session IS NONE ORDER BY created_at ASC, id ASC")
+            .bind(("table", self.table.clone()))
+            .await?;
+        let legacy = result
+            .take::<Vec<Value>>(0)?
+            .into_iter()
+            .map(|mut record| {
+                // Messages were created with their id as the key of their record, which is
+                // kept as their id.
+                if let Some(record) = record.as_object_mut() {
+                    record.remove("id");
+                    match record.remove("key") {
+                        Some(Value::String(key)) => {
+                            record.insert("id".into(), Value::String(key));
+                        }
+                        Some(key @ Value::Number(_)) => {
+                            record.insert("id".into(), Value::String(key.to_string()));
+                        }
+                        _ => {}
+                    }
+                }
+                Message::from_stored(1, record)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if legacy.is_empty() {
+            return Ok(());
+        }
+
+        self.db
+            .query(format!(
+                "
+                BEGIN TRANSACTION;
+                DELETE type::table($table) WHERE session IS NONE;
+                LET $last = array::max((SELECT VALUE seq FROM type::table($table) WHERE session = $session)) ?? 0;
+                {CREATE_RECORDS}
+                COMMIT TRANSACTION;
+                "
+            ))
+            .bind(("table", self.table.clone()))
+            .bind(("session", DEFAULT_SESSION))
+            .bind(("records", records(legacy)?))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    /// Returns at most `limit` messages of the session, skipping the first `offset` ones.
+    pub async fn messages_page(&self, offset: usize, limit: usize) -> Result<Vec<Message>> {
+        let mut result = self
+            .db
+            .query("SELECT seq, version, message FROM type::table($table) WHERE session = $session ORDER BY seq ASC LIMIT $limit START $offset")
+            .bind(("table", self.table.clone()))
+            .bind(("session", self.session.clone()))
+            .bind(("limit", limit))
+            .bind(("offset", offset))
+            .await?;
+        let messages: Vec<StoredMessage> = result.take(0)?;
+        messages
+            .into_iter()
+            .map(StoredMessage::into_message)
+            .collect()
+    }
+
+    /// Deletes the message of the session with the given id.
+    pub async fn delete_message(&self, id: &str) -> Result<()> {
+        self.db
+            .query("DELETE type::thing($table, [$session, $id])")
+            .bind(("table", self.table.clone()))
+            .bind(("id", id.to_string()))
+            .bind(("session", self.session.clone()))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    /// Lists the sessions of the table, most recently active first.
+    pub async fn sessions(&self) -> Result<Vec<Session>> {
+        let mut result = self
+            .db
+            .query("SELECT session AS id, count() AS messages, math::max(created_at) AS last_activity FROM type::table($table) GROUP BY session")
+            .bind(("table", self.table.clone()))
+            .await?;
+        let mut sessions: Vec<Session> = result.take(0)?;
+        sessions.sort_by(|a, b| {
+            b.last_activity
+                .cmp(&a.last_activity)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        Ok(sessions)
+    }
 }
 
 pub struct SurrealDbMemoryBuilder {
     db: Option<Arc<Surreal<Any>>>,
     table: Option<String>,
+    session: Option<String>,
 }
 
 impl SurrealDbMemoryBuilder {
@@ -34,6 +231,15 @@ impl SurrealDbMemoryBuilder {
         self
     }
 
+    /// Sets the session of the memory, `default` if not set.
+    pub fn with_session<S>(mut self, session: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.session = Some(session.into());
+        self
+    }
+
     pub fn build(self) -> Result<SurrealDbMemory> {
         let db = self
             .db
@@ -42,33 +248,338 @@ impl SurrealDbMemoryBuilder {
             .table
             .ok_or_else(|| ferrochain::anyhow::anyhow!("Table not set"))?;
 
-        Ok(SurrealDbMemory { db, table })
+        Ok(SurrealDbMemory {
+            db,
+            table,
+            session: self.session.unwrap_or_else(|| DEFAULT_SESSION.into()),
+        })
     }
 }
 
 #[ferrochain::async_trait]
 impl Memory for SurrealDbMemory {
+    /// Stores the messages in a single transaction, after the last message of the session.
+    ///
+    /// Messages without an id are given a random one, and messages without a creation time
+    /// are stamped with the current time.
     async fn add_messages(&self, messages: Vec<Message>) -> Result<()> {
-        let sql = format!("CREATE {} CONTENT $content", self.table);
-        for message in messages {
-            self.db
-                .query(sql.clone())
-                .bind(("content", message))
-                .await?;
+        if messages.is_empty() {
+            return Ok(());
         }
+
+        self.db
+            .query(format!(
+                "
+                BEGIN TRANSACTION;
+                LET $last = array::max((SELECT VALUE seq FROM type::table($table) WHERE session = $session)) ?? 0;
+                {CREATE_RECORDS}
+                COMMIT TRANSACTION;
+                "
+            ))
+            .bind(("table", self.table.clone()))
+            .bind(("session", self.session.clone()))
+            .bind(("records", records(messages)?))
+            .await?
+            .check()?;
         Ok(())
     }
 
     async fn messages(&self) -> Result<Vec<Message>> {
-        let sql = format!("SELECT * FROM {}", self.table);
-        let mut result = self.db.query(sql).await?;
-        let messages: Vec<Message> = result.take(0)?;
-        Ok(messages)
+        let mut result = self
+            .db
+            .query("SELECT seq, version, message FROM type::table($table) WHERE session = $session ORDER BY seq ASC")
+            .bind(("table", self.table.clone()))
+            .bind(("session", self.session.clone()))
+            .await?;
+        let messages: Vec<StoredMessage> = result.take(0)?;
+        messages
+            .into_iter()
+            .map(StoredMessage::into_message)
+            .collect()
     }
 
     async fn clear(&self) -> Result<()> {
-        let sql = format!("DELETE {}", self.table);
-        self.db.query(sql).await?;
+        self.db
+            .query("DELETE type::table($table) WHERE session = $session")
+            .bind(("table", self.table.clone()))
+            .bind(("session", self.session.clone()))
+            .await?
+            .check()?;
         Ok(())
     }
+
+    /// Deletes the messages of the session and stores the new ones in a single transaction.
+    async fn replace_messages(&self, messages: Vec<Message>) -> Result<()> {
+        self.db
+            .query(format!(
+                "
+                BEGIN TRANSACTION;
+                DELETE type::table($table) WHERE session = $session;
+                LET $last = 0;
+                {CREATE_RECORDS}
+                COMMIT TRANSACTION;
+                "
+            ))
+            .bind(("table", self.table.clone()))
+            .bind(("session", self.session.clone()))
+            .bind(("records", records(messages)?))
+            .await?
+            .check()?;
+        Ok(())
+    }
+}
+
+/// Prepares messages to be stored, giving a random id to those without one and stamping
+/// those without a creation time with the current time.
+fn records(messages: Vec<Message>) -> Result<Vec<Value>> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    Ok(messages
+        .into_iter()
+        .enumerate()
+        .map(|(offset, mut message)| {
+            let id = message
+                .id
+                .get_or_insert_with(|| Uuid::new_v4().to_string())
+                .clone();
+            let created_at = *message.created_at.get_or_insert(now);
+            json!({
+                "id": id,
+                "offset": offset,
+                "created_at": created_at,
+                "version": MESSAGE_VERSION,
+                "message": message,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use ferrochain::message::{Content, Role};
+    use surrealdb::engine::any::connect;
+
+    use super::*;
+
+    async fn db() -> Arc<Surreal<Any>> {
+        let db = connect("mem://").await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        Arc::new(db)
+    }
+
+    async fn memory() -> SurrealDbMemory {
+        let memory = SurrealDbMemory::builder()
+            .with_surrealdb(db().await)
+            .with_table("messages".into())
+            .with_session("a")
+            .build()
+            .unwrap();
+        memory.migrate().await.unwrap();
+        memory
+    }
+
+    fn message(text: &str, created_at: u64) -> Message {
+        Message {
+            role: Role::User,
+            content: vec![text.into()],
+            created_at: Some(created_at),
+            ..Default::default()
+        }
+    }
+
+    fn texts(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|message| match &message.content[0] {
+                Content::Text { text } => text.clone(),
+                _ => panic!("expected a text message"),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_ordered_and_isolated() {
+        let a = memory().await;
+        let b = a.session("b");
+
+        let batch = (0..20).map(|i| message(&i.to_string(), 10)).collect();
+        a.add_messages(batch).await.unwrap();
+        a.add_messages(vec![message("20", 30)]).await.unwrap();
+        b.add_messages(vec![message("hello", 20)]).await.unwrap();
+
+        let messages = a.messages().await.unwrap();
+        assert_eq!(
+            texts(&messages),
+            (0..21).map(|i| i.to_string()).collect::<Vec<_>>()
+        );
+        assert!(messages.iter().all(|message| message.id.is_some()));
+        assert_eq!(texts(&b.messages().await.unwrap()), ["hello"]);
+
+        let page = a.messages_page(5, 3).await.unwrap();
+        assert_eq!(texts(&page), ["5", "6", "7"]);
+
+        assert_eq!(
+            a.sessions().await.unwrap(),
+            [
+                Session {
+                    id: "a".into(),
+                    messages: 21,
+                    last_activity: 30,
+                },
+                Session {
+                    id: "b".into(),
+                    messages: 1,
+                    last_activity: 20,
+                },
+            ]
+        );
+
+        b.clear().await.unwrap();
+        assert!(b.messages().await.unwrap().is_empty());
+        assert_eq!(a.messages().await.unwrap().len(), 21);
+    }
+
+    #[tokio::test]
+    async fn test_delete_message() {
+        let memory = memory().await;
+        memory
+            .add_messages(vec![message("first", 1), message("second", 2)])
+            .await
+            .unwrap();
+
+        let messages = memory.messages().await.unwrap();
+        memory
+            .delete_message(messages[0].id.as_deref().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(texts(&memory.messages().await.unwrap()), ["second"]);
+
+        // Messages added after a deletion still come last.
+        memory
+            .add_messages(vec![message("third", 3)])
+            .await
+            .unwrap();
+        assert_eq!(
+            texts(&memory.messages().await.unwrap()),
+            ["second", "third"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replace_messages() {
+        let a = memory().await;
+        let b = a.session("b");
+        a.add_messages(vec![message("1", 1), message("2", 2)])
+            .await
+            .unwrap();
+        b.add_messages(vec![message("hello", 3)]).await.unwrap();
+
+        let kept = a.messages().await.unwrap().pop().unwrap();
+        a.replace_messages(vec![message("summary", 4), kept])
+            .await
+            .unwrap();
+        assert_eq!(texts(&a.messages().await.unwrap()), ["summary", "2"]);
+        assert_eq!(texts(&b.messages().await.unwrap()), ["hello"]);
+
+        a.add_messages(vec![message("3", 5)]).await.unwrap();
+        assert_eq!(texts(&a.messages().await.unwrap()), ["summary", "2", "3"]);
+    }
+
+    #[tokio::test]
+    async fn test_message_ids_are_scoped_to_sessions() {
+        let a = memory().await;
+        let b = a.session("b");
+        let with_id = |text: &str| Message {
+            id: Some("same".into()),
+            ..message(text, 1)
+        };
+
+        a.add_messages(vec![with_id("in a")]).await.unwrap();
+        b.add_messages(vec![with_id("in b")]).await.unwrap();
+        assert_eq!(texts(&a.messages().await.unwrap()), ["in a"]);
+        assert_eq!(texts(&b.messages().await.unwrap()), ["in b"]);
+
+        b.delete_message("same").await.unwrap();
+        assert_eq!(texts(&a.messages().await.unwrap()), ["in a"]);
+        assert!(b.messages().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unversioned_records_are_upgraded() {
+        let db = db().await;
+        db.query("CREATE type::thing('messages', ['a', 'x']) CONTENT $content")
+            .bind((
+                "content",
+                json!({
+                    "session": "a",
+                    "seq": 1,
+                    "created_at": 1,
+                    "message": { "role": "human", "content": [{ "type": "text", "text": "hi" }] },
+                }),
+            ))
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let memory = SurrealDbMemory::builder()
+            .with_surrealdb(db)
+            .with_table("messages".into())
+            .with_session("a")
+            .build()
+            .unwrap();
+        let messages = memory.messages().await.unwrap();
+        assert_eq!(messages[0].role, Role::User);
+        assert_eq!(texts(&messages), ["hi"]);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_messages_are_migrated() {
+        let db = db().await;
+        // Earlier versions stored each message as a record of its own, keyed on its id.
+        for (id, text, created_at) in [("b", "2", 1), ("c", "0", 0), ("a", "1", 1)] {
+            db.query("CREATE messages CONTENT $content")
+                .bind((
+                    "content",
+                    Message {
+                        id: Some(id.into()),
+                        ..message(text, created_at)
+                    },
+                ))
+                .await
+                .unwrap()
+                .check()
+                .unwrap();
+        }
+
+        let memory = SurrealDbMemory::builder()
+            .with_surrealdb(db)
+            .with_table("messages".into())
+            .build()
+            .unwrap();
+        memory.migrate().await.unwrap();
+        // Ordered by creation time, then by id, keeping their ids.
+        let messages = memory.messages().await.unwrap();
+        assert_eq!(texts(&messages), ["0", "1", "2"]);
+        assert_eq!(
+            messages
+                .iter()
+                .map(|message| message.id.as_deref())
+                .collect::<Vec<_>>(),
+            [Some("c"), Some("a"), Some("b")]
+        );
+
+        // Migrating again leaves the messages as they are.
+        memory.migrate().await.unwrap();
+        assert_eq!(texts(&memory.messages().await.unwrap()), ["0", "1", "2"]);
+
+        memory.add_messages(vec![message("3", 2)]).await.unwrap();
+        assert_eq!(
+            texts(&memory.messages().await.unwrap()),
+            ["0", "1", "2", "3"]
+        );
+
+        // The ids of migrated messages still refer to them.
+        memory.delete_message("a").await.unwrap();
+        assert_eq!(texts(&memory.messages().await.unwrap()), ["0", "2", "3"]);
+    }
 }
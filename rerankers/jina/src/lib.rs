@@ -2,12 +2,14 @@ use std::sync::Arc;
 
 use ferrochain::{
     anyhow::{anyhow, Result},
+    config::{ComponentFactory, Components},
     document::{Document, StoredDocument},
     reranker::Reranker,
     vector_store::Similarity,
 };
 use http_client::HttpClient;
 use jina_sdk::{DocumentType, Jina, JinaBuilder, QueryType, RerankRequest, RerankerModel};
+use serde_json::Value;
 
 pub struct JinaReranker {
     client: Jina,
@@ -111,3 +113,51 @@ impl Reranker for JinaReranker {
             })?)
     }
 }
+
+/// Builds [`JinaReranker`]s out of their configuration, see [`ferrochain::config`].
+#[derive(Clone, Default)]
+pub struct JinaRerankerFactory {
+    http_client: Option<Arc<dyn HttpClient>>,
+}
+
+impl JinaRerankerFactory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_http_client(mut self, http_client: Arc<dyn HttpClient>) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JinaRerankerParams {
+    api_key: String,
+    base_url: Option<String>,
+    model: RerankerModel,
+    top_n: Option<usize>,
+}
+
+#[ferrochain::async_trait]
+impl ComponentFactory<dyn Reranker> for JinaRerankerFactory {
+    async fn build(&self, params: Value, _: &Components) -> Result<Box<dyn Reranker>> {
+        let params: JinaRerankerParams = serde_json::from_value(params)?;
+
+        let mut builder = JinaReranker::builder()
+            .with_api_key(params.api_key)
+            .with_model(params.model);
+        if let Some(http_client) = self.http_client.clone() {
+            builder = builder.with_http_client(http_client);
+        }
+        if let Some(base_url) = params.base_url {
+            builder = builder.with_base_url(base_url);
+        }
+        if let Some(top_n) = params.top_n {
+            builder = builder.with_top_n(top_n);
+        }
+
+        Ok(Box::new(builder.build()?))
+    }
+}
@@ -2,11 +2,13 @@ use std::sync::Arc;
 
 use ferrochain::{
     anyhow::{anyhow, Result},
+    config::{ComponentFactory, Components},
     document::{Document, StoredDocument},
     reranker::Reranker,
     vector_store::Similarity,
 };
 use http_client::HttpClient;
+use serde_json::Value;
 pub use voyageai_sdk::RerankModel;
 use voyageai_sdk::{RerankRequest, VoyageAi, VoyageAiBuilder};
 
@@ -117,3 +119,55 @@ impl VoyageAiRerankerBuilder {
         })
     }
 }
+
+/// Builds [`VoyageAiReranker`]s out of their configuration, see [`ferrochain::config`].
+#[derive(Clone, Default)]
+pub struct VoyageAiRerankerFactory {
+    http_client: Option<Arc<dyn HttpClient>>,
+}
+
+impl VoyageAiRerankerFactory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_http_client(mut self, http_client: Arc<dyn HttpClient>) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VoyageAiRerankerParams {
+    api_key: String,
+    base_url: Option<String>,
+    model: RerankModel,
+    top_k: Option<u32>,
+    truncation: Option<bool>,
+}
+
+#[ferrochain::async_trait]
+impl ComponentFactory<dyn Reranker> for VoyageAiRerankerFactory {
+    async fn build(&self, params: Value, _: &Components) -> Result<Box<dyn Reranker>> {
+        let params: VoyageAiRerankerParams = serde_json::from_value(params)?;
+
+        let mut builder = VoyageAiReranker::builder()
+            .with_api_key(params.api_key)
+            .with_model(params.model);
+        if let Some(http_client) = self.http_client.clone() {
+            builder = builder.with_http_client(http_client);
+        }
+        if let Some(base_url) = params.base_url {
+            builder = builder.with_base_url(base_url);
+        }
+        if let Some(top_k) = params.top_k {
+            builder = builder.with_top_k(top_k);
+        }
+        if let Some(truncation) = params.truncation {
+            builder = builder.with_truncation(truncation);
+        }
+
+        Ok(Box::new(builder.build()?))
+    }
+}
@@ -2,8 +2,14 @@ use std::{collections::HashMap, sync::Arc};
 
 pub use exa_sdk::SearchKind;
 use exa_sdk::{Exa, ExaBuilder, SearchContent, SearchContentText, SearchRequest};
-use ferrochain::{anyhow::Result, document::Document, retriever::Retriever};
+use ferrochain::{
+    anyhow::Result,
+    config::{ComponentFactory, Components},
+    document::Document,
+    retriever::Retriever,
+};
 use http_client::HttpClient;
+use serde_json::Value;
 
 pub struct ExaRetriever {
     client: Exa,
@@ -171,3 +177,64 @@ impl ExaRetrieverBuilder {
         })
     }
 }
+
+/// Builds [`ExaRetriever`]s out of their configuration, see [`ferrochain::config`].
+#[derive(Clone, Default)]
+pub struct ExaRetrieverFactory {
+    http_client: Option<Arc<dyn HttpClient>>,
+}
+
+impl ExaRetrieverFactory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_http_client(mut self, http_client: Arc<dyn HttpClient>) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExaRetrieverParams {
+    api_key: String,
+    base_url: Option<String>,
+    use_autoprompt: Option<bool>,
+    kind: Option<SearchKind>,
+    num_results: Option<u32>,
+    include_domains: Option<Vec<String>>,
+    exclude_domains: Option<Vec<String>>,
+}
+
+#[ferrochain::async_trait]
+impl ComponentFactory<dyn Retriever> for ExaRetrieverFactory {
+    async fn build(&self, params: Value, _: &Components) -> Result<Box<dyn Retriever>> {
+        let params: ExaRetrieverParams = serde_json::from_value(params)?;
+
+        let mut builder = ExaRetriever::builder().with_api_key(params.api_key);
+        if let Some(http_client) = self.http_client.clone() {
+            builder = builder.with_http_client(http_client);
+        }
+        if let Some(base_url) = params.base_url {
+            builder = builder.with_base_url(base_url);
+        }
+        if let Some(use_autoprompt) = params.use_autoprompt {
+            builder = builder.with_use_autoprompt(use_autoprompt);
+        }
+        if let Some(kind) = params.kind {
+            builder = builder.with_kind(kind);
+        }
+        if let Some(num_results) = params.num_results {
+            builder = builder.with_num_results(num_results);
+        }
+        if let Some(include_domains) = params.include_domains {
+            builder = builder.with_include_domains(include_domains);
+        }
+        if let Some(exclude_domains) = params.exclude_domains {
+            builder = builder.with_exclude_domains(exclude_domains);
+        }
+
+        Ok(Box::new(builder.build()?))
+    }
+}
@@ -2,11 +2,13 @@ use std::collections::HashMap;
 
 use ferrochain::{
     anyhow::{anyhow, Result},
+    config::{ComponentFactory, Components},
     document::Document,
     retriever::Retriever,
 };
 pub use firecrawl::scrape::ScrapeFormats;
 use firecrawl::{scrape::ScrapeOptions, FirecrawlApp};
+use serde_json::Value;
 
 pub struct FirecrawlRetriever {
     client: FirecrawlApp,
@@ -79,3 +81,28 @@ impl FirecrawlRetrieverBuilder {
         })
     }
 }
+
+/// Builds [`FirecrawlRetriever`]s out of their configuration, see [`ferrochain::config`].
+#[derive(Clone, Default)]
+pub struct FirecrawlRetrieverFactory;
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FirecrawlRetrieverParams {
+    api_key: String,
+    formats: Option<Vec<ScrapeFormats>>,
+}
+
+#[ferrochain::async_trait]
+impl ComponentFactory<dyn Retriever> for FirecrawlRetrieverFactory {
+    async fn build(&self, params: Value, _: &Components) -> Result<Box<dyn Retriever>> {
+        let params: FirecrawlRetrieverParams = serde_json::from_value(params)?;
+
+        let mut builder = FirecrawlRetriever::builder().with_api_key(params.api_key);
+        if let Some(formats) = params.formats {
+            builder = builder.with_formats(formats);
+        }
+
+        Ok(Box::new(builder.build()?))
+    }
+}
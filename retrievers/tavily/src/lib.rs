@@ -1,6 +1,12 @@
 use std::collections::HashMap;
 
-use ferrochain::{anyhow::Result, document::Document, retriever::Retriever};
+use ferrochain::{
+    anyhow::Result,
+    config::{ComponentFactory, Components},
+    document::Document,
+    retriever::Retriever,
+};
+use serde_json::Value;
 pub use tavily_sdk::search::{SearchDepth, Topic};
 use tavily_sdk::{search::TavilySearchParams, Tavily, TavilyBuilder};
 
@@ -180,3 +186,64 @@ impl TavilyRetrieverBuilder {
         })
     }
 }
+
+/// Builds [`TavilyRetriever`]s out of their configuration, see [`ferrochain::config`].
+#[derive(Clone, Default)]
+pub struct TavilyRetrieverFactory;
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TavilyRetrieverParams {
+    api_key: String,
+    base_url: Option<String>,
+    search_depth: Option<SearchDepth>,
+    topic: Option<Topic>,
+    include_domains: Option<Vec<String>>,
+    exclude_domains: Option<Vec<String>>,
+    days: Option<u32>,
+    max_results: Option<u32>,
+    include_images: Option<bool>,
+    include_answer: Option<bool>,
+    include_raw_content: Option<bool>,
+}
+
+#[ferrochain::async_trait]
+impl ComponentFactory<dyn Retriever> for TavilyRetrieverFactory {
+    async fn build(&self, params: Value, _: &Components) -> Result<Box<dyn Retriever>> {
+        let params: TavilyRetrieverParams = serde_json::from_value(params)?;
+
+        let mut builder = TavilyRetriever::builder().with_api_key(params.api_key);
+        if let Some(base_url) = params.base_url {
+            builder = builder.with_base_url(base_url);
+        }
+        if let Some(search_depth) = params.search_depth {
+            builder = builder.with_search_depth(search_depth);
+        }
+        if let Some(topic) = params.topic {
+            builder = builder.with_topic(topic);
+        }
+        if let Some(include_domains) = params.include_domains {
+            builder = builder.with_include_domains(include_domains);
+        }
+        if let Some(exclude_domains) = params.exclude_domains {
+            builder = builder.with_exclude_domains(exclude_domains);
+        }
+        if let Some(days) = params.days {
+            builder = builder.with_days(days);
+        }
+        if let Some(max_results) = params.max_results {
+            builder = builder.with_max_results(max_results);
+        }
+        if let Some(include_images) = params.include_images {
+            builder = builder.with_include_images(include_images);
+        }
+        if let Some(include_answer) = params.include_answer {
+            builder = builder.with_include_answer(include_answer);
+        }
+        if let Some(include_raw_content) = params.include_raw_content {
+            builder = builder.with_include_raw_content(include_raw_content);
+        }
+
+        Ok(Box::new(builder.build()?))
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::{stream, FutureExt, Stream, StreamExt, TryStreamExt};
+
+use crate::{
+    completion::{Completion, CompletionOptions},
+    message::Message,
+};
+
+/// A request of a batch, identified by an id unique within the batch.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct BatchRequest {
+    pub custom_id: String,
+    pub messages: Vec<Message>,
+    #[serde(default)]
+    pub options: CompletionOptions,
+}
+
+impl BatchRequest {
+    pub fn new<S>(custom_id: S, messages: Vec<Message>) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            custom_id: custom_id.into(),
+            messages,
+            options: CompletionOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: CompletionOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchState {
+    #[default]
+    InProgress,
+    Canceling,
+    /// Every request has been processed, and the results are available.
+    Ended,
+}
+
+/// The progress of a batch, with the number of requests in each state.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct BatchStatus {
+    pub state: BatchState,
+    pub processing: u64,
+    pub succeeded: u64,
+    pub errored: u64,
+    pub canceled: u64,
+    pub expired: u64,
+}
+
+/// The result of a request of a batch.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct BatchResult {
+    pub custom_id: String,
+    pub outcome: BatchOutcome,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BatchOutcome {
+    Succeeded { message: Message },
+    Errored { error: String },
+    Canceled,
+    Expired,
+}
+
+pub type BatchResults = Pin<Box<dyn Stream<Item = Result<BatchResult>> + Send>>;
+
+/// Completes many conversations at once, for workloads which don't need their answers right
+/// away.
+#[async_trait]
+pub trait BatchCompletion: Send + Sync {
+    /// Submits the requests, returning the id of the batch.
+    async fn submit(&self, requests: Vec<BatchRequest>) -> Result<String>;
+
+    async fn status(&self, batch_id: &str) -> Result<BatchStatus>;
+
+    /// Streams the results of an ended batch, in no particular order.
+    async fn results(&self, batch_id: &str) -> Result<BatchResults>;
+
+    /// Polls the status of the batch every `interval`, until it ends.
+    async fn wait(&self, batch_id: &str, interval: Duration) -> Result<BatchStatus> {
+        loop {
+            let status = self.status(batch_id).await?;
+            if status.state == BatchState::Ended {
+                return Ok(status);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+/// A [`BatchCompletion`] running the requests of its batches through any [`Completion`], at
+/// most `concurrency` at a time.
+///
+/// Batches run in the background as soon as they are submitted, and their results are kept
+/// in memory until they are retrieved: once [`BatchCompletion::results`] has returned them,
+/// the batch is forgotten.
+pub struct ConcurrentBatchCompletion {
+    completion: Arc<dyn Completion>,
+    concurrency: usize,
+    batches: Arc<Mutex<HashMap<String, Batch>>>,
+    next_id: AtomicU64,
+}
+
+#[derive(Default)]
+struct Batch {
+    status: BatchStatus,
+    results: Vec<BatchResult>,
+}
+
+impl ConcurrentBatchCompletion {
+    pub fn new(completion: Arc<dyn Completion>, concurrency: usize) -> Self {
+        Self {
+            completion,
+            concurrency: concurrency.max(1),
+            batches: Default::default(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    fn with_batch<T>(&self, batch_id: &str, f: impl FnOnce(&Batch) -> Result<T>) -> Result<T> {
+        let batches = self
+            .batches
+            .lock()
+            .map_err(|_| anyhow!("the batches are poisoned"))?;
+        let batch = batches
+            .get(batch_id)
+            .ok_or_else(|| anyhow!("Batch not found: {}", batch_id))?;
+        f(batch)
+    }
+}
+
+#[async_trait]
+impl BatchCompletion for ConcurrentBatchCompletion {
+    async fn submit(&self, requests: Vec<BatchRequest>) -> Result<String> {
+        let mut custom_ids = HashSet::new();
+        for request in &requests {
+            if !custom_ids.insert(request.custom_id.as_str()) {
+                return Err(anyhow!("duplicate custom id: {}", request.custom_id));
+            }
+        }
+
+        let batch_id = format!("batch_{}", self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.batches
+            .lock()
+            .map_err(|_| anyhow!("the batches are poisoned"))?
+            .insert(
+                batch_id.clone(),
+                Batch {
+                    status: BatchStatus {
+                        processing: requests.len() as u64,
+                        ..Default::default()
+                    },
+                    results: vec![],
+                },
+            );
+
+        let completion = self.completion.clone();
+        let batches = self.batches.clone();
+        let id = batch_id.clone();
+        let concurrency = self.concurrency;
+        tokio::spawn(async move {
+            let mut results = stream::iter(requests)
+                .map(|request| {
+                    let completion = completion.clone();
+                    async move {
+                        // A panicking request fails on its own, instead of the whole batch
+                        // never ending.
+                        let completed = AssertUnwindSafe(complete(
+                            completion.as_ref(),
+                            request.messages,
+                            request.options,
+                        ))
+                        .catch_unwind()
+                        .await;
+                        let outcome = match completed {
+                            Ok(Ok(message)) => BatchOutcome::Succeeded { message },
+                            Ok(Err(error)) => BatchOutcome::Errored {
+                                error: format!("{error:#}"),
+                            },
+                            Err(panic) => BatchOutcome::Errored {
+                                error: panic_message(panic.as_ref()),
+                            },
+                        };
+                        BatchResult {
+                            custom_id: request.custom_id,
+                            outcome,
+                        }
+                    }
+                })
+                .buffer_unordered(concurrency);
+
+            while let Some(result) = results.next().await {
+                let Ok(mut batches) = batches.lock() else {
+                    return;
+                };
+                let Some(batch) = batches.get_mut(&id) else {
+                    return;
+                };
+                batch.status.processing -= 1;
+                match result.outcome {
+                    BatchOutcome::Succeeded { .. } => batch.status.succeeded += 1,
+                    _ => batch.status.errored += 1,
+                }
+                batch.results.push(result);
+            }
+
+            if let Ok(mut batches) = batches.lock() {
+                if let Some(batch) = batches.get_mut(&id) {
+                    batch.status.state = BatchState::Ended;
+                }
+            }
+        });
+
+        Ok(batch_id)
+    }
+
+    async fn status(&self, batch_id: &str) -> Result<BatchStatus> {
+        self.with_batch(batch_id, |batch| Ok(batch.status.clone()))
+    }
+
+    async fn results(&self, batch_id: &str) -> Result<BatchResults> {
+        let mut batches = self
+            .batches
+            .lock()
+            .map_err(|_| anyhow!("the batches are poisoned"))?;
+        match batches.get(batch_id) {
+            Some(batch) if batch.status.state == BatchState::Ended => {}
+            Some(_) => return Err(anyhow!("the batch {} is still in progress", batch_id)),
+            None => return Err(anyhow!("Batch not found: {}", batch_id)),
+        }
+        let results = batches.remove(batch_id).unwrap_or_default().results;
+        Ok(stream::iter(results.into_iter().map(Ok)).boxed())
+    }
+}
+
+/// Completes the messages of a request into a single message.
+async fn complete(
+    completion: &dyn Completion,
+    messages: Vec<Message>,
+    options: CompletionOptions,
+) -> Result<Message> {
+    let messages: Vec<Message> = completion
+        .complete_with_options(messages, options)
+        .await?
+        .try_collect()
+        .await?;
+    messages
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("the completion returned no message"))
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown error");
+    format!("the completion panicked: {}", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::{
+        completion::{CompletionResponse, StreamEvent, StreamEventEnvelope},
+        message::{Content, Role},
+    };
+
+    /// Answers with the text of the last message in upper case, failing on `fail` and
+    /// panicking on `panic`, and records the largest number of requests it handled at once.
+    #[derive(Default)]
+    struct Shout {
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Completion for Shout {
+        async fn complete(&self, messages: Vec<Message>) -> Result<CompletionResponse> {
+            if text(&messages[messages.len() - 1]) == "panic" {
+                panic!("shouting too loud");
+            }
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+
+            let text = text(&messages[messages.len() - 1]);
+            if text == "fail" {
+                return Err(anyhow!("failed").context("cannot shout"));
+            }
+            Ok(CompletionResponse::new(
+                stream::iter([Ok(StreamEventEnvelope {
+                    index: 0,
+                    event: StreamEvent::Delta {
+                        index: 0,
+                        inner: vec![text.to_uppercase().into()],
+                    },
+                })])
+                .boxed(),
+            ))
+        }
+    }
+
+    fn text(message: &Message) -> &str {
+        match &message.content[0] {
+            Content::Text { text } => text,
+            _ => panic!("expected text"),
+        }
+    }
+
+    fn request(custom_id: &str, text: &str) -> BatchRequest {
+        BatchRequest::new(
+            custom_id,
+            vec![Message {
+                role: Role::User,
+                content: vec![text.into()],
+                ..Default::default()
+            }],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_batch_completion() {
+        let completion = Arc::new(Shout::default());
+        let batch = ConcurrentBatchCompletion::new(completion.clone(), 3);
+
+        let mut requests = (0..10)
+            .map(|i| request(&format!("request-{}", i), &format!("hello {}", i)))
+            .collect::<Vec<_>>();
+        requests.push(request("failing", "fail"));
+        requests.push(request("panicking", "panic"));
+        let batch_id = batch.submit(requests).await.unwrap();
+
+        let status = batch
+            .wait(&batch_id, Duration::from_millis(5))
+            .await
+            .unwrap();
+        assert_eq!(
+            status,
+            BatchStatus {
+                state: BatchState::Ended,
+                succeeded: 10,
+                errored: 2,
+                ..Default::default()
+            }
+        );
+        assert!(completion.max_running.load(Ordering::SeqCst) <= 3);
+
+        let results = batch
+            .results(&batch_id)
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 12);
+        // The results are only kept until they are retrieved.
+        assert!(batch.status(&batch_id).await.is_err());
+        for result in results {
+            match (result.custom_id.as_str(), result.outcome) {
+                ("failing", BatchOutcome::Errored { error }) => {
+                    assert_eq!(error, "cannot shout: failed")
+                }
+                ("panicking", BatchOutcome::Errored { error }) => {
+                    assert_eq!(error, "the completion panicked: shouting too loud")
+                }
+                (custom_id, BatchOutcome::Succeeded { message }) => assert_eq!(
+                    text(&message),
+                    format!("HELLO {}", custom_id.trim_start_matches("request-"))
+                ),
+                (custom_id, _) => panic!("unexpected outcome for {}", custom_id),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_batches() {
+        let batch = ConcurrentBatchCompletion::new(Arc::new(Shout::default()), 1);
+        assert!(batch
+            .submit(vec![request("a", "hello"), request("a", "again")])
+            .await
+            .is_err());
+        assert!(batch.status("batch_0").await.is_err());
+
+        let batch_id = batch.submit(vec![request("a", "hello")]).await.unwrap();
+        assert!(batch.results(&batch_id).await.is_err());
+    }
+}
@@ -1,13 +1,41 @@
-use std::ops::BitOr;
+use std::{ops::BitOr, pin::Pin, sync::Arc};
 
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::{stream, Stream, StreamExt};
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 
+use crate::{
+    completion::{Completion, StreamEventEnvelope},
+    message::{Content, Message},
+};
+
+/// An event emitted while a [`Chain`] is being streamed.
+#[derive(Clone, Debug)]
+pub enum ChainEvent {
+    /// The output of a step which is not the last one of the chain.
+    Intermediate(Value),
+    /// An event streamed by a [`Completion`] running as part of the chain.
+    Completion(StreamEventEnvelope<Vec<Content>>),
+    /// The output of the whole chain.
+    Final(Value),
+}
+
+pub type ChainStream = Pin<Box<dyn Stream<Item = Result<ChainEvent>> + Send>>;
+
 #[async_trait]
 pub trait Chain: Send + Sync {
     async fn run(&self, input: Value) -> Result<Value>;
+
+    /// Runs the chain, yielding the events produced along the way.
+    ///
+    /// The default implementation awaits [`Chain::run`] and yields its output as a single
+    /// [`ChainEvent::Final`] event.
+    async fn stream(&self, input: Value) -> Result<ChainStream> {
+        let output = self.run(input).await?;
+        Ok(stream::once(async move { Ok(ChainEvent::Final(output)) }).boxed())
+    }
 }
 
 #[async_trait]
@@ -43,4 +71,155 @@ impl Chain for Chained {
         let output = self.current.run(input).await?;
         self.next.run(output).await
     }
+
+    async fn stream(&self, input: Value) -> Result<ChainStream> {
+        let output = self.current.run(input).await?;
+        let next = self.next.stream(output.clone()).await?;
+        Ok(
+            stream::once(async move { Ok(ChainEvent::Intermediate(output)) })
+                .chain(next)
+                .boxed(),
+        )
+    }
+}
+
+/// A [`Chain`] step backed by a [`Completion`].
+///
+/// The input is expected to be a list of [`Message`], and the output is the list of messages
+/// produced by the completion. When streamed, each completion event is forwarded as a
+/// [`ChainEvent::Completion`] before the assembled messages are yielded as
+/// [`ChainEvent::Final`].
+pub struct CompletionChain {
+    completion: Arc<dyn Completion>,
+}
+
+impl CompletionChain {
+    pub fn new(completion: Arc<dyn Completion>) -> Self {
+        Self { completion }
+    }
+}
+
+#[async_trait]
+impl Chain for CompletionChain {
+    async fn run(&self, input: Value) -> Result<Value> {
+        let messages: Vec<Message> = serde_json::from_value(input)?;
+        let output = self.completion.i(messages).await?;
+        Ok(serde_json::to_value(output)?)
+    }
+
+    async fn stream(&self, input: Value) -> Result<ChainStream> {
+        let messages: Vec<Message> = serde_json::from_value(input)?;
+        let response = self.completion.complete(messages).await?;
+
+        Ok(stream::unfold(
+            Some((response, Vec::<Message>::new())),
+            |state| async move {
+                let (mut response, mut messages) = state?;
+                match response.next().await {
+                    Some(Ok(event)) => {
+                        messages.extend(std::iter::once(event.clone()));
+                        Some((
+                            Ok(ChainEvent::Completion(event)),
+                            Some((response, messages)),
+                        ))
+                    }
+                    Some(Err(err)) => Some((Err(err), None)),
+                    None => Some((
+                        serde_json::to_value(messages)
+                            .map(ChainEvent::Final)
+                            .map_err(Into::into),
+                        None,
+                    )),
+                }
+            },
+        )
+        .boxed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        completion::{CompletionResponse, StreamEvent},
+        message::Role,
+    };
+
+    struct Wrap(&'static str);
+
+    #[async_trait]
+    impl Chain for Wrap {
+        async fn run(&self, input: Value) -> Result<Value> {
+            Ok(
+                json!([{ "role": "user", "content": [{ "type": "text", "text": format!("{}{}", self.0, input.as_str().unwrap()) }] }]),
+            )
+        }
+    }
+
+    struct Echo;
+
+    #[async_trait]
+    impl Completion for Echo {
+        async fn complete(&self, messages: Vec<Message>) -> Result<CompletionResponse> {
+            let deltas = messages[0]
+                .content
+                .iter()
+                .filter_map(|content| match content {
+                    Content::Text { text } => Some(text.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            let mut events = vec![StreamEventEnvelope {
+                index: 0,
+                event: StreamEvent::Start {
+                    index: 0,
+                    model: "echo".into(),
+                    role: Role::Assistant,
+                    inner: vec![],
+                },
+            }];
+            events.extend(deltas.into_iter().map(|text| StreamEventEnvelope {
+                index: 0,
+                event: StreamEvent::Delta {
+                    index: 0,
+                    inner: vec![text.into()],
+                },
+            }));
+
+            Ok(stream::iter(events.into_iter().map(Ok)).boxed().into())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chained_stream_forwards_completion_events() {
+        let chain = (Box::new(Wrap("say: ")) as Box<dyn Chain>)
+            | (Box::new(CompletionChain::new(Arc::new(Echo))) as Box<dyn Chain>);
+
+        let events: Vec<ChainEvent> = chain
+            .stream(json!("hello"))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert!(matches!(events.first(), Some(ChainEvent::Intermediate(_))));
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| matches!(event, ChainEvent::Completion(_)))
+                .count(),
+            2
+        );
+        let Some(ChainEvent::Final(output)) = events.last() else {
+            panic!("expected a final event");
+        };
+        assert_eq!(output[0]["role"], "assistant");
+        assert_eq!(output[0]["content"][0]["text"], "say: hello");
+        assert_eq!(chain.run(json!("hello")).await.unwrap(), *output);
+    }
 }
@@ -7,7 +7,7 @@ use anyhow::Result;
 use async_trait::async_trait;
 use futures::{Stream, TryStreamExt};
 
-use crate::message::{Content, Message};
+use crate::message::{Content, Message, Role};
 
 pub trait CompletionModel {
     fn id(&self) -> &str;
@@ -46,7 +46,7 @@ pub enum StreamEvent<Inner> {
     Start {
         index: u64,
         model: String,
-        role: String,
+        role: Role,
         inner: Inner,
     },
     Delta {
@@ -55,9 +55,23 @@ pub enum StreamEvent<Inner> {
     },
     End {
         stop_reason: String,
+        /// The tokens used by the request, when reported by the provider.
+        usage: Option<Usage>,
     },
 }
 
+/// The number of tokens used by a request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Usage {
+    /// The input tokens processed without reading or writing the prompt cache.
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// The input tokens read from the prompt cache.
+    pub cache_read_tokens: u64,
+    /// The input tokens written to the prompt cache.
+    pub cache_write_tokens: u64,
+}
+
 pub struct CompletionResponse(
     Pin<Box<dyn Stream<Item = Result<StreamEventEnvelope<Vec<Content>>>> + Send>>,
 );
@@ -109,15 +123,25 @@ impl Extend<StreamEventEnvelope<Vec<Content>>> for Vec<Message> {
                 }
                 StreamEvent::Delta { inner, .. } => {
                     for content in inner {
-                        if let Some(Content::Text { text: last_text }) = message.content.last_mut()
-                        {
-                            if let Content::Text { text: new_text } = content {
-                                last_text.push_str(&new_text);
-                            } else {
-                                message.content.push(content);
+                        match (message.content.last_mut(), content) {
+                            (Some(Content::Text { text }), Content::Text { text: delta }) => {
+                                text.push_str(&delta);
+                            }
+                            // Thinking is streamed in pieces, its signature coming last.
+                            (
+                                Some(Content::Thinking {
+                                    thinking,
+                                    signature: signature @ None,
+                                }),
+                                Content::Thinking {
+                                    thinking: delta,
+                                    signature: delta_signature,
+                                },
+                            ) => {
+                                thinking.push_str(&delta);
+                                *signature = delta_signature;
                             }
-                        } else {
-                            message.content.push(content);
+                            (_, content) => message.content.push(content),
                         }
                     }
                 }
@@ -127,9 +151,170 @@ impl Extend<StreamEventEnvelope<Vec<Content>>> for Vec<Message> {
     }
 }
 
+/// Controls whether and how the model uses tools.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// The model decides whether to use a tool.
+    #[default]
+    Auto,
+    /// The model must use one of the available tools.
+    Any,
+    /// The model must not use any tool.
+    None,
+    /// The model must use the named tool.
+    Tool { name: String },
+}
+
+/// The tools made available to the model for a single request.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct ToolSelection {
+    pub choice: ToolChoice,
+    /// The names of the registered tools to advertise, or every registered tool if `None`.
+    pub tools: Option<Vec<String>>,
+    /// Whether to cache the tool definitions, for providers supporting prompt caching.
+    #[serde(default)]
+    pub cache_breakpoint: bool,
+}
+
+impl ToolSelection {
+    /// Forces the model to use the named tool, advertising it alone.
+    pub fn tool<S>(name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        let name = name.into();
+        Self {
+            tools: Some(vec![name.clone()]),
+            choice: ToolChoice::Tool { name },
+            ..Default::default()
+        }
+    }
+
+    /// Advertises only the named tools.
+    pub fn only<I, S>(tools: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            choice: ToolChoice::Auto,
+            tools: Some(tools.into_iter().map(Into::into).collect()),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_choice(mut self, choice: ToolChoice) -> Self {
+        self.choice = choice;
+        self
+    }
+
+    /// Marks a cache breakpoint after the tool definitions, so that providers supporting
+    /// prompt caching reuse them across requests.
+    pub fn with_cache_breakpoint(mut self, cache_breakpoint: bool) -> Self {
+        self.cache_breakpoint = cache_breakpoint;
+        self
+    }
+}
+
+/// The settings of a single request, overriding those the completion was built with.
+///
+/// Settings left unset fall back to those of the completion.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct CompletionOptions {
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<usize>,
+    /// The system prompt, replacing that of the completion.
+    pub system: Option<Vec<Content>>,
+    pub tools: Option<ToolSelection>,
+    /// Sequences which stop the generation when produced by the model.
+    pub stop_sequences: Option<Vec<String>>,
+    pub top_k: Option<u32>,
+    pub top_p: Option<f32>,
+    /// An opaque id of the end user, which providers may use to detect abuse.
+    pub user_id: Option<String>,
+}
+
+impl CompletionOptions {
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn with_system(mut self, system: Vec<Content>) -> Self {
+        self.system = Some(system);
+        self
+    }
+
+    pub fn with_tools(mut self, tools: ToolSelection) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    pub fn with_stop_sequences<I, S>(mut self, stop_sequences: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.stop_sequences = Some(stop_sequences.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn with_top_k(mut self, top_k: u32) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    pub fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn with_user_id<S>(mut self, user_id: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.user_id = Some(user_id.into());
+        self
+    }
+}
+
 #[async_trait]
 pub trait Completion: Send + Sync {
     async fn complete(&self, messages: Vec<Message>) -> Result<CompletionResponse>;
+
+    /// Completes `messages` using only the tools described by `tools`.
+    ///
+    /// Completions which don't support tools ignore the selection.
+    async fn complete_with_tools(
+        &self,
+        messages: Vec<Message>,
+        tools: ToolSelection,
+    ) -> Result<CompletionResponse> {
+        let _ = tools;
+        self.complete(messages).await
+    }
+
+    /// Completes `messages` with the settings of `options`.
+    ///
+    /// Completions which don't support per-request settings ignore them, except for the
+    /// tools, which are passed on to [`Completion::complete_with_tools`].
+    async fn complete_with_options(
+        &self,
+        messages: Vec<Message>,
+        options: CompletionOptions,
+    ) -> Result<CompletionResponse> {
+        match options.tools {
+            Some(tools) => self.complete_with_tools(messages, tools).await,
+            None => self.complete(messages).await,
+        }
+    }
     async fn i(&self, messages: Vec<Message>) -> Result<Vec<Message>> {
         Ok(self.complete(messages).await?.try_collect().await?)
     }
@@ -144,3 +329,107 @@ pub trait StructuredCompletion {
         messages: Vec<Message>,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent<Self::Output>>>>>>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(inner: Vec<Content>) -> StreamEventEnvelope<Vec<Content>> {
+        StreamEventEnvelope {
+            index: 0,
+            event: StreamEvent::Delta { index: 0, inner },
+        }
+    }
+
+    fn thinking(thinking: &str, signature: Option<&str>) -> Content {
+        Content::Thinking {
+            thinking: thinking.into(),
+            signature: signature.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn test_extend_merges_deltas() {
+        let mut messages: Vec<Message> = vec![];
+        messages.extend([
+            StreamEventEnvelope {
+                index: 0,
+                event: StreamEvent::Start {
+                    index: 0,
+                    model: "model".into(),
+                    role: Role::Assistant,
+                    inner: vec![],
+                },
+            },
+            delta(vec![thinking("Let me ", None)]),
+            delta(vec![thinking("think.", None)]),
+            delta(vec![thinking("", Some("signature"))]),
+            delta(vec![thinking("Again.", Some("other"))]),
+            delta(vec!["Hello".into()]),
+            delta(vec![", world".into()]),
+        ]);
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].role, Role::Assistant);
+        assert_eq!(
+            serde_json::to_value(&messages[0].content).unwrap(),
+            serde_json::to_value([
+                thinking("Let me think.", Some("signature")),
+                thinking("Again.", Some("other")),
+                "Hello, world".into(),
+            ])
+            .unwrap()
+        );
+    }
+
+    /// Answers with the names of the tools it was given, if any.
+    struct ToolNames;
+
+    #[async_trait]
+    impl Completion for ToolNames {
+        async fn complete(&self, _: Vec<Message>) -> Result<CompletionResponse> {
+            self.complete_with_tools(vec![], ToolSelection::only(["none"]))
+                .await
+        }
+
+        async fn complete_with_tools(
+            &self,
+            _: Vec<Message>,
+            tools: ToolSelection,
+        ) -> Result<CompletionResponse> {
+            let names = tools.tools.unwrap_or_default().join(",");
+            Ok(CompletionResponse::new(Box::pin(futures::stream::iter([
+                Ok(delta(vec![names.into()])),
+            ]))))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_complete_with_options_passes_tools() {
+        let text = |messages: Vec<Message>| match &messages[0].content[0] {
+            Content::Text { text } => text.clone(),
+            _ => panic!("expected text"),
+        };
+
+        let options = CompletionOptions::default()
+            .with_temperature(0.5)
+            .with_tools(ToolSelection::only(["search"]));
+        let messages = ToolNames
+            .complete_with_options(vec![], options)
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(text(messages), "search");
+
+        let messages = ToolNames
+            .complete_with_options(vec![], CompletionOptions::default())
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(text(messages), "none");
+    }
+}
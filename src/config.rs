@@ -0,0 +1,883 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::{
+    chain::{Chain, CompletionChain},
+    completion::Completion,
+    embedding::Embedder,
+    reranker::Reranker,
+    retriever::{Retriever, RetrieverTool},
+    tool::{DynamicTool, ToolProvider},
+    vector_store::{VectorStore, VectorStoreTool},
+};
+
+/// The configuration of a single component.
+///
+/// `kind` selects the factory used to build the component, while every other field is passed
+/// to that factory as its parameters.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ComponentConfig {
+    pub kind: String,
+    #[serde(flatten)]
+    pub params: serde_json::Map<String, Value>,
+}
+
+/// A chain made of a sequence of steps, executed one after the other.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainConfig {
+    pub steps: Vec<ComponentConfig>,
+}
+
+/// Declarative description of the components used by a service.
+///
+/// Each section maps a component name to its configuration. Components can refer to
+/// components of the previous sections by name (e.g. a vector store referring to an
+/// embedder), which are built in the following order: embedders, rerankers, vector stores,
+/// retrievers, tools, completions and chains.
+///
+/// String values can reference environment variables as `${NAME}`, or `${NAME:-default}` to
+/// fall back to a default value when the variable is unset. Their values stay strings, unless
+/// a reference making up the whole value asks for a type, as in `${PORT:int}`, `${RATIO:float}`
+/// or `${ENABLED:bool:-false}`.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub embedders: BTreeMap<String, ComponentConfig>,
+    #[serde(default)]
+    pub rerankers: BTreeMap<String, ComponentConfig>,
+    #[serde(default)]
+    pub vector_stores: BTreeMap<String, ComponentConfig>,
+    #[serde(default)]
+    pub retrievers: BTreeMap<String, ComponentConfig>,
+    #[serde(default)]
+    pub tools: BTreeMap<String, ComponentConfig>,
+    #[serde(default)]
+    pub completions: BTreeMap<String, ComponentConfig>,
+    #[serde(default)]
+    pub chains: BTreeMap<String, ChainConfig>,
+}
+
+impl Config {
+    /// Loads the configuration from a YAML or JSON file, depending on its extension.
+    ///
+    /// YAML files need the `config` feature.
+    pub fn from_path<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read configuration file {}", path.display()))?;
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::from_json_str(&source),
+            #[cfg(feature = "config")]
+            Some("yaml" | "yml") => Self::from_yaml_str(&source),
+            #[cfg(not(feature = "config"))]
+            Some("yaml" | "yml") => bail!(
+                "cannot read {}, YAML configuration needs the `config` feature",
+                path.display()
+            ),
+            _ => bail!(
+                "cannot infer the format of {}, expected a .json, .yaml or .yml extension",
+                path.display()
+            ),
+        }
+    }
+
+    pub fn from_json_str(source: &str) -> Result<Self> {
+        Self::from_json_str_with_env(source, |name| std::env::var(name).ok())
+    }
+
+    /// Loads the configuration from JSON, looking the variables it references up with `env`
+    /// instead of in the environment.
+    pub fn from_json_str_with_env<F>(source: &str, env: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        Self::from_value(
+            serde_json::from_str(source).context("invalid JSON configuration")?,
+            &env,
+        )
+    }
+
+    #[cfg(feature = "config")]
+    pub fn from_yaml_str(source: &str) -> Result<Self> {
+        Self::from_yaml_str_with_env(source, |name| std::env::var(name).ok())
+    }
+
+    /// Loads the configuration from YAML, looking the variables it references up with `env`
+    /// instead of in the environment.
+    #[cfg(feature = "config")]
+    pub fn from_yaml_str_with_env<F>(source: &str, env: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        Self::from_value(
+            serde_norway::from_str(source).context("invalid YAML configuration")?,
+            &env,
+        )
+    }
+
+    fn from_value(mut value: Value, env: &dyn Fn(&str) -> Option<String>) -> Result<Self> {
+        interpolate(&mut value, &mut String::new(), env)?;
+        serde_json::from_value(value).context("invalid configuration")
+    }
+}
+
+/// Replaces `${NAME}` and `${NAME:-default}` in every string of `value` with the matching
+/// variables looked up with `env`, `$${` standing for a literal `${`.
+///
+/// A string made of a single typed reference, such as `${PORT:int}`, is converted to that type.
+fn interpolate(
+    value: &mut Value,
+    path: &mut String,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<()> {
+    match value {
+        Value::String(string) => {
+            let kind = whole_reference(string)
+                .and_then(|reference| parse_reference(reference).1)
+                .map(str::to_string);
+            let interpolated =
+                interpolate_str(string, env).with_context(|| format!("at `{}`", path))?;
+            *value = match kind {
+                Some(kind) => {
+                    convert(interpolated, &kind).with_context(|| format!("at `{}`", path))?
+                }
+                None => Value::String(interpolated),
+            };
+        }
+        Value::Array(values) => {
+            for (index, value) in values.iter_mut().enumerate() {
+                let len = path.len();
+                path.push_str(&format!("[{}]", index));
+                interpolate(value, path, env)?;
+                path.truncate(len);
+            }
+        }
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let len = path.len();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(key);
+                interpolate(value, path, env)?;
+                path.truncate(len);
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// The reference making up the whole of `source`, without its `${` and `}`.
+fn whole_reference(source: &str) -> Option<&str> {
+    source
+        .strip_prefix("${")
+        .filter(|rest| rest.find('}') == Some(rest.len() - 1))
+        .map(|rest| &rest[..rest.len() - 1])
+}
+
+/// Splits a reference into its variable name, type and default value.
+fn parse_reference(reference: &str) -> (&str, Option<&str>, Option<&str>) {
+    let (name, default) = match reference.split_once(":-") {
+        Some((name, default)) => (name, Some(default)),
+        None => (reference, None),
+    };
+    match name.split_once(':') {
+        Some((name, kind)) => (name, Some(kind), default),
+        None => (name, None, default),
+    }
+}
+
+fn interpolate_str(source: &str, env: &dyn Fn(&str) -> Option<String>) -> Result<String> {
+    let whole = whole_reference(source).is_some();
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            output.push_str(&rest[..start - 1]);
+            output.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        output.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            bail!("unterminated variable reference in `{}`", source);
+        };
+
+        let (name, kind, default) = parse_reference(&rest[start + 2..start + end]);
+        if let Some(kind) = kind.filter(|_| !whole) {
+            bail!(
+                "`${{{}:{}}}` must make up the whole value to be typed",
+                name,
+                kind
+            );
+        }
+
+        match (env(name), default) {
+            (Some(value), _) => output.push_str(&value),
+            (None, Some(default)) => output.push_str(default),
+            (None, None) => bail!("environment variable `{}` is not set", name),
+        }
+
+        rest = &rest[start + end + 1..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Converts `text` to the type `kind` of its reference.
+fn convert(text: String, kind: &str) -> Result<Value> {
+    let invalid = || anyhow!("`{}` is not a valid {}", text, kind);
+    Ok(match kind {
+        "int" => Value::from(text.trim().parse::<i64>().map_err(|_| invalid())?),
+        "float" => serde_json::Number::from_f64(text.trim().parse().map_err(|_| invalid())?)
+            .map(Value::Number)
+            .ok_or_else(invalid)?,
+        "bool" => Value::Bool(text.trim().parse().map_err(|_| invalid())?),
+        "str" => Value::String(text),
+        _ => bail!(
+            "unknown type `{}`, expected one of: bool, float, int, str",
+            kind
+        ),
+    })
+}
+
+/// Builds a component of type `T` out of its configuration parameters.
+///
+/// Factories can look up previously built components, such as the embedder of a vector
+/// store, through [`Components`].
+#[async_trait]
+pub trait ComponentFactory<T: ?Sized>: Send + Sync {
+    async fn build(&self, params: Value, components: &Components) -> Result<Box<T>>;
+
+    /// Builds the component configured under `key`, for the factories whose components
+    /// default to being named after it. Defaults to [`build`](Self::build).
+    async fn build_keyed(
+        &self,
+        _key: &str,
+        params: Value,
+        components: &Components,
+    ) -> Result<Box<T>> {
+        self.build(params, components).await
+    }
+}
+
+struct Factories<T: ?Sized>(HashMap<String, Arc<dyn ComponentFactory<T>>>);
+
+impl<T: ?Sized> Clone for Factories<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: ?Sized> Default for Factories<T> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+
+impl<T: ?Sized> Factories<T> {
+    async fn build(
+        &self,
+        section: &str,
+        name: &str,
+        config: &ComponentConfig,
+        components: &Components,
+    ) -> Result<Box<T>> {
+        let Some(factory) = self.0.get(&config.kind) else {
+            let mut kinds = self.0.keys().map(String::as_str).collect::<Vec<_>>();
+            kinds.sort();
+            bail!(
+                "{}.{}: unknown kind `{}`, expected one of: {}",
+                section,
+                name,
+                config.kind,
+                kinds.join(", ")
+            );
+        };
+
+        factory
+            .build_keyed(name, Value::Object(config.params.clone()), components)
+            .await
+            .with_context(|| format!("{}.{}: cannot build `{}`", section, name, config.kind))
+    }
+}
+
+/// A registry of named component factories, used to build [`Components`] out of a [`Config`].
+#[derive(Clone)]
+pub struct Registry {
+    embedders: Factories<dyn Embedder>,
+    rerankers: Factories<dyn Reranker>,
+    vector_stores: Factories<dyn VectorStore>,
+    retrievers: Factories<dyn Retriever>,
+    tools: Factories<dyn DynamicTool>,
+    completions: Factories<dyn Completion>,
+    chains: Factories<dyn Chain>,
+}
+
+impl Registry {
+    /// Creates a registry with the built-in `completion` chain step, which runs the
+    /// completion named by its `completion` parameter, and the built-in `retriever` and
+    /// `vector_store` tools, which expose the retriever or vector store named by their
+    /// `retriever` or `vector_store` parameter and named after their key unless given a
+    /// `name`.
+    pub fn new() -> Self {
+        let mut registry = Self {
+            embedders: Default::default(),
+            rerankers: Default::default(),
+            vector_stores: Default::default(),
+            retrievers: Default::default(),
+            tools: Default::default(),
+            completions: Default::default(),
+            chains: Default::default(),
+        };
+        registry.register_chain("completion", CompletionChainFactory);
+        registry.register_tool("retriever", RetrieverToolFactory);
+        registry.register_tool("vector_store", VectorStoreToolFactory);
+        registry
+    }
+
+    pub fn register_embedder<S, F>(&mut self, kind: S, factory: F) -> &mut Self
+    where
+        S: Into<String>,
+        F: ComponentFactory<dyn Embedder> + 'static,
+    {
+        self.embedders.0.insert(kind.into(), Arc::new(factory));
+        self
+    }
+
+    pub fn register_reranker<S, F>(&mut self, kind: S, factory: F) -> &mut Self
+    where
+        S: Into<String>,
+        F: ComponentFactory<dyn Reranker> + 'static,
+    {
+        self.rerankers.0.insert(kind.into(), Arc::new(factory));
+        self
+    }
+
+    pub fn register_vector_store<S, F>(&mut self, kind: S, factory: F) -> &mut Self
+    where
+        S: Into<String>,
+        F: ComponentFactory<dyn VectorStore> + 'static,
+    {
+        self.vector_stores.0.insert(kind.into(), Arc::new(factory));
+        self
+    }
+
+    pub fn register_retriever<S, F>(&mut self, kind: S, factory: F) -> &mut Self
+    where
+        S: Into<String>,
+        F: ComponentFactory<dyn Retriever> + 'static,
+    {
+        self.retrievers.0.insert(kind.into(), Arc::new(factory));
+        self
+    }
+
+    pub fn register_tool<S, F>(&mut self, kind: S, factory: F) -> &mut Self
+    where
+        S: Into<String>,
+        F: ComponentFactory<dyn DynamicTool> + 'static,
+    {
+        self.tools.0.insert(kind.into(), Arc::new(factory));
+        self
+    }
+
+    pub fn register_completion<S, F>(&mut self, kind: S, factory: F) -> &mut Self
+    where
+        S: Into<String>,
+        F: ComponentFactory<dyn Completion> + 'static,
+    {
+        self.completions.0.insert(kind.into(), Arc::new(factory));
+        self
+    }
+
+    pub fn register_chain<S, F>(&mut self, kind: S, factory: F) -> &mut Self
+    where
+        S: Into<String>,
+        F: ComponentFactory<dyn Chain> + 'static,
+    {
+        self.chains.0.insert(kind.into(), Arc::new(factory));
+        self
+    }
+
+    /// Builds every component described by `config`.
+    pub async fn build(&self, config: &Config) -> Result<Components> {
+        let mut components = Components::default();
+
+        for (name, component) in &config.embedders {
+            let embedder = self
+                .embedders
+                .build("embedders", name, component, &components)
+                .await?;
+            components.embedders.insert(name.clone(), embedder.into());
+        }
+
+        for (name, component) in &config.rerankers {
+            let reranker = self
+                .rerankers
+                .build("rerankers", name, component, &components)
+                .await?;
+            components.rerankers.insert(name.clone(), reranker.into());
+        }
+
+        for (name, component) in &config.vector_stores {
+            let vector_store = self
+                .vector_stores
+                .build("vector_stores", name, component, &components)
+                .await?;
+            components
+                .vector_stores
+                .insert(name.clone(), vector_store.into());
+        }
+
+        for (name, component) in &config.retrievers {
+            let retriever = self
+                .retrievers
+                .build("retrievers", name, component, &components)
+                .await?;
+            components.retrievers.insert(name.clone(), retriever.into());
+        }
+
+        for (name, component) in &config.tools {
+            let tool = self
+                .tools
+                .build("tools", name, component, &components)
+                .await?;
+            components.tools.insert(name.clone(), tool.into());
+        }
+
+        for (name, component) in &config.completions {
+            let completion = self
+                .completions
+                .build("completions", name, component, &components)
+                .await?;
+            components
+                .completions
+                .insert(name.clone(), completion.into());
+        }
+
+        for (name, chain) in &config.chains {
+            let mut steps = Vec::with_capacity(chain.steps.len());
+            for (index, step) in chain.steps.iter().enumerate() {
+                steps.push(
+                    self.chains
+                        .build(
+                            "chains",
+                            &format!("{}.steps[{}]", name, index),
+                            step,
+                            &components,
+                        )
+                        .await?,
+                );
+            }
+
+            let chain = steps
+                .into_iter()
+                .reduce(|current, next| current | next)
+                .ok_or_else(|| anyhow!("chains.{}: a chain needs at least one step", name))?;
+            components.chains.insert(name.clone(), chain.into());
+        }
+
+        Ok(components)
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The components built by a [`Registry`], indexed by name.
+#[derive(Clone, Default)]
+pub struct Components {
+    embedders: HashMap<String, Arc<dyn Embedder>>,
+    rerankers: HashMap<String, Arc<dyn Reranker>>,
+    vector_stores: HashMap<String, Arc<dyn VectorStore>>,
+    retrievers: HashMap<String, Arc<dyn Retriever>>,
+    tools: HashMap<String, Arc<dyn DynamicTool>>,
+    completions: HashMap<String, Arc<dyn Completion>>,
+    chains: HashMap<String, Arc<dyn Chain>>,
+}
+
+fn lookup<T: ?Sized>(
+    components: &HashMap<String, Arc<T>>,
+    section: &str,
+    name: &str,
+) -> Result<Arc<T>> {
+    components
+        .get(name)
+        .cloned()
+        .ok_or_else(|| anyhow!("unknown {} `{}`", section, name))
+}
+
+impl Components {
+    pub fn embedder(&self, name: &str) -> Result<Arc<dyn Embedder>> {
+        lookup(&self.embedders, "embedder", name)
+    }
+
+    pub fn reranker(&self, name: &str) -> Result<Arc<dyn Reranker>> {
+        lookup(&self.rerankers, "reranker", name)
+    }
+
+    pub fn vector_store(&self, name: &str) -> Result<Arc<dyn VectorStore>> {
+        lookup(&self.vector_stores, "vector store", name)
+    }
+
+    pub fn retriever(&self, name: &str) -> Result<Arc<dyn Retriever>> {
+        lookup(&self.retrievers, "retriever", name)
+    }
+
+    pub fn tool(&self, name: &str) -> Result<Arc<dyn DynamicTool>> {
+        lookup(&self.tools, "tool", name)
+    }
+
+    /// Creates a [`ToolProvider`] holding every configured tool.
+    pub fn tool_provider(&self) -> Result<ToolProvider> {
+        let mut provider = ToolProvider::new();
+        for tool in self.tools.values() {
+            provider.register_dynamic(tool.clone())?;
+        }
+        Ok(provider)
+    }
+
+    pub fn completion(&self, name: &str) -> Result<Arc<dyn Completion>> {
+        lookup(&self.completions, "completion", name)
+    }
+
+    pub fn chain(&self, name: &str) -> Result<Arc<dyn Chain>> {
+        lookup(&self.chains, "chain", name)
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CompletionChainParams {
+    completion: String,
+}
+
+struct CompletionChainFactory;
+
+#[async_trait]
+impl ComponentFactory<dyn Chain> for CompletionChainFactory {
+    async fn build(&self, params: Value, components: &Components) -> Result<Box<dyn Chain>> {
+        let params: CompletionChainParams = serde_json::from_value(params)?;
+        Ok(Box::new(CompletionChain::new(
+            components.completion(&params.completion)?,
+        )))
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RetrieverToolParams {
+    retriever: String,
+    name: Option<String>,
+    description: String,
+}
+
+struct RetrieverToolFactory;
+
+impl RetrieverToolFactory {
+    /// Builds the tool, named by its `name` parameter, else by `key`, else by its retriever.
+    fn build_tool(
+        params: Value,
+        key: Option<&str>,
+        components: &Components,
+    ) -> Result<Box<dyn DynamicTool>> {
+        let params: RetrieverToolParams = serde_json::from_value(params)?;
+        let retriever = components.retriever(&params.retriever)?;
+        Ok(Box::new(
+            RetrieverTool::builder()
+                .with_retriever(retriever)
+                .with_name(
+                    params
+                        .name
+                        .or_else(|| key.map(str::to_string))
+                        .unwrap_or(params.retriever),
+                )
+                .with_description(params.description)
+                .build(),
+        ))
+    }
+}
+
+#[async_trait]
+impl ComponentFactory<dyn DynamicTool> for RetrieverToolFactory {
+    async fn build(&self, params: Value, components: &Components) -> Result<Box<dyn DynamicTool>> {
+        Self::build_tool(params, None, components)
+    }
+
+    async fn build_keyed(
+        &self,
+        key: &str,
+        params: Value,
+        components: &Components,
+    ) -> Result<Box<dyn DynamicTool>> {
+        Self::build_tool(params, Some(key), components)
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VectorStoreToolParams {
+    vector_store: String,
+    name: Option<String>,
+    description: String,
+}
+
+struct VectorStoreToolFactory;
+
+impl VectorStoreToolFactory {
+    /// Builds the tool, named by its `name` parameter, else by `key`, else by its vector
+    /// store.
+    fn build_tool(
+        params: Value,
+        key: Option<&str>,
+        components: &Components,
+    ) -> Result<Box<dyn DynamicTool>> {
+        let params: VectorStoreToolParams = serde_json::from_value(params)?;
+        let vector_store = components.vector_store(&params.vector_store)?;
+        Ok(Box::new(
+            VectorStoreTool::builder()
+                .with_vector_store(vector_store)
+                .with_name(
+                    params
+                        .name
+                        .or_else(|| key.map(str::to_string))
+                        .unwrap_or(params.vector_store),
+                )
+                .with_description(params.description)
+                .build(),
+        ))
+    }
+}
+
+#[async_trait]
+impl ComponentFactory<dyn DynamicTool> for VectorStoreToolFactory {
+    async fn build(&self, params: Value, components: &Components) -> Result<Box<dyn DynamicTool>> {
+        Self::build_tool(params, None, components)
+    }
+
+    async fn build_keyed(
+        &self,
+        key: &str,
+        params: Value,
+        components: &Components,
+    ) -> Result<Box<dyn DynamicTool>> {
+        Self::build_tool(params, Some(key), components)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{document::Document, embedding::Embedding};
+
+    struct ConstantEmbedder(Vec<f32>);
+
+    #[async_trait]
+    impl Embedder for ConstantEmbedder {
+        async fn embed(&self, chunks: Vec<String>) -> Result<Vec<Embedding>> {
+            Ok(chunks.iter().map(|_| self.0.clone().into()).collect())
+        }
+    }
+
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct ConstantEmbedderParams {
+        api_key: String,
+        value: f32,
+    }
+
+    struct ConstantEmbedderFactory;
+
+    #[async_trait]
+    impl ComponentFactory<dyn Embedder> for ConstantEmbedderFactory {
+        async fn build(&self, params: Value, _: &Components) -> Result<Box<dyn Embedder>> {
+            let params: ConstantEmbedderParams = serde_json::from_value(params)?;
+            assert_eq!(params.api_key, "secret");
+            Ok(Box::new(ConstantEmbedder(vec![params.value])))
+        }
+    }
+
+    #[cfg(feature = "config")]
+    #[tokio::test]
+    async fn test_build_from_yaml_with_env_interpolation() {
+        let config = Config::from_yaml_str_with_env(
+            "
+            embedders:
+              default:
+                kind: constant
+                api_key: ${API_KEY}
+                value: 1.5
+            ",
+            |name| (name == "API_KEY").then(|| "secret".to_string()),
+        )
+        .unwrap();
+
+        let mut registry = Registry::new();
+        registry.register_embedder("constant", ConstantEmbedderFactory);
+        let components = registry.build(&config).await.unwrap();
+
+        let embeddings = components
+            .embedder("default")
+            .unwrap()
+            .embed(vec!["hello".into()])
+            .await
+            .unwrap();
+        assert_eq!(embeddings[0].to_vec(), vec![1.5]);
+        assert!(components.embedder("missing").is_err());
+    }
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "PORT" => Some("8080".into()),
+            "FLAG" => Some("true".into()),
+            "API_KEY" => Some("0123".into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let mut value = serde_json::json!({
+            "port": "${PORT:int}",
+            "port_text": "${PORT}",
+            "url": "http://localhost:${PORT}",
+            "enabled": "${FLAG:bool}",
+            "flag": "${FLAG}",
+            "api_key": "${API_KEY}",
+            "ratio": "${UNSET:float:-0.5}",
+            "name": "${UNSET:-docs}",
+            "empty": "${UNSET:-}",
+            "template": "$${PORT} is ${PORT}",
+            "escaped": "$${PORT}",
+        });
+        interpolate(&mut value, &mut String::new(), &env).unwrap();
+
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "port": 8080,
+                "port_text": "8080",
+                "url": "http://localhost:8080",
+                "enabled": true,
+                "flag": "true",
+                "api_key": "0123",
+                "ratio": 0.5,
+                "name": "docs",
+                "empty": "",
+                "template": "${PORT} is 8080",
+                "escaped": "${PORT}",
+            })
+        );
+
+        for (value, expected) in [
+            ("${API_KEY:bool}", "`0123` is not a valid bool"),
+            ("${PORT:port}", "unknown type `port`"),
+            ("port ${PORT:int}", "must make up the whole value"),
+            ("${UNSET}", "environment variable `UNSET` is not set"),
+        ] {
+            let error =
+                interpolate(&mut serde_json::json!(value), &mut String::new(), &env).unwrap_err();
+            assert!(format!("{:#}", error).contains(expected), "{}", value);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validation_errors() {
+        let error = Config::from_json_str_with_env(
+            r#"{ "embedders": { "a": { "kind": "x", "key": "${UNSET}" } } }"#,
+            env,
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", error).contains("embedders.a.key"));
+
+        let config =
+            Config::from_json_str(r#"{ "embedders": { "a": { "kind": "unknown" } } }"#).unwrap();
+        let mut registry = Registry::new();
+        registry.register_embedder("constant", ConstantEmbedderFactory);
+        let error = registry.build(&config).await.err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "embedders.a: unknown kind `unknown`, expected one of: constant"
+        );
+
+        let config = Config::from_json_str(
+            r#"{ "chains": { "answer": { "steps": [{ "kind": "completion", "completion": "missing" }] } } }"#,
+        )
+        .unwrap();
+        let error = registry.build(&config).await.err().unwrap();
+        assert!(format!("{:#}", error).contains("unknown completion `missing`"));
+    }
+
+    struct EchoRetriever;
+
+    #[async_trait]
+    impl Retriever for EchoRetriever {
+        async fn retrieve(&self, query: &str) -> Result<Vec<Document>> {
+            Ok(vec![Document {
+                content: query.into(),
+                metadata: Default::default(),
+            }])
+        }
+    }
+
+    struct EchoRetrieverFactory;
+
+    #[async_trait]
+    impl ComponentFactory<dyn Retriever> for EchoRetrieverFactory {
+        async fn build(&self, _: Value, _: &Components) -> Result<Box<dyn Retriever>> {
+            Ok(Box::new(EchoRetriever))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_tools() {
+        let config = Config::from_json_str(
+            r#"{
+                "retrievers": { "docs": { "kind": "echo" } },
+                "tools": {
+                    "search": {
+                        "kind": "retriever",
+                        "retriever": "docs",
+                        "description": "the documentation"
+                    },
+                    "lookup": {
+                        "kind": "retriever",
+                        "retriever": "docs",
+                        "name": "docs lookup",
+                        "description": "the documentation"
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let mut registry = Registry::new();
+        registry.register_retriever("echo", EchoRetrieverFactory);
+        let components = registry.build(&config).await.unwrap();
+
+        let provider = components.tool_provider().unwrap();
+        let tools = provider.list().map(|tool| tool.name).collect::<Vec<_>>();
+        assert_eq!(tools, ["retriever_docs_lookup", "retriever_search"]);
+    }
+}
@@ -1,8 +1,12 @@
+extern crate self as ferrochain;
+
 pub mod code_embedding;
 
 pub mod chain;
 
+pub mod batch_completion;
 pub mod completion;
+pub mod config;
 pub mod document;
 pub mod document_loader;
 pub mod embedding;
@@ -12,10 +16,14 @@ pub mod memory;
 pub mod message;
 pub mod reranker;
 pub mod retriever;
+pub mod runnable;
 pub mod splitter;
 pub mod tool;
+pub mod tool_selector;
 pub mod vector_store;
 
 pub use anyhow;
 pub use async_trait::async_trait;
+pub use ferrochain_macros::tool;
 pub use futures;
+pub use serde_json;
@@ -1,7 +1,21 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
 use anyhow::Result;
 use async_trait::async_trait;
+use futures::lock::Mutex;
+use indoc::formatdoc;
+use serde_json::{json, Value};
 
-use crate::message::Message;
+use crate::{
+    completion::Completion,
+    document::Document,
+    message::{Content, Message, Role},
+    vector_store::VectorStore,
+};
 
 /// Most LLM applications have a conversational interface.
 ///
@@ -23,4 +37,1011 @@ pub trait Memory: Send + Sync {
     async fn add_messages(&self, messages: Vec<Message>) -> Result<()>;
 
     async fn clear(&self) -> Result<()>;
+
+    /// Replaces every stored message with `messages`.
+    ///
+    /// The default implementation clears the memory before adding the messages, losing them
+    /// all if adding fails. Memories able to replace them in a single step should do so.
+    async fn replace_messages(&self, messages: Vec<Message>) -> Result<()> {
+        self.clear().await?;
+        self.add_messages(messages).await
+    }
+}
+
+/// Estimates the number of tokens of a message.
+pub trait TokenCounter: Send + Sync {
+    fn count(&self, message: &Message) -> usize;
+}
+
+impl<F> TokenCounter for F
+where
+    F: Fn(&Message) -> usize + Send + Sync,
+{
+    fn count(&self, message: &Message) -> usize {
+        self(message)
+    }
+}
+
+/// Approximates the number of tokens as a quarter of the number of characters of the
+/// serialized content, which is close enough for English text with most tokenizers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ApproximateTokenCounter;
+
+impl TokenCounter for ApproximateTokenCounter {
+    fn count(&self, message: &Message) -> usize {
+        let characters = message
+            .content
+            .iter()
+            .map(|content| match content {
+                Content::Text { text } => text.chars().count(),
+                content => serde_json::to_string(content).map_or(0, |json| json.len()),
+            })
+            .sum::<usize>();
+
+        // Each message has an overhead of a few tokens for its role and delimiters.
+        4 + characters.div_ceil(4)
+    }
+}
+
+/// A [`Memory`] returning only the last messages of an inner memory.
+pub struct WindowMemory<M> {
+    inner: M,
+    size: usize,
+    keep_first: bool,
+}
+
+impl<M> WindowMemory<M> {
+    /// Returns at most `size` messages of `inner`.
+    pub fn new(inner: M, size: usize) -> Self {
+        Self {
+            inner,
+            size,
+            keep_first: false,
+        }
+    }
+
+    /// Always returns the first system or user message, counting towards the window size.
+    pub fn with_keep_first(mut self, keep_first: bool) -> Self {
+        self.keep_first = keep_first;
+        self
+    }
+}
+
+#[async_trait]
+impl<M> Memory for WindowMemory<M>
+where
+    M: Memory,
+{
+    async fn messages(&self) -> Result<Vec<Message>> {
+        let mut count = 0;
+        Ok(recent_messages(
+            self.inner.messages().await?,
+            self.keep_first,
+            |_| {
+                count += 1;
+                count <= self.size
+            },
+        ))
+    }
+
+    async fn add_messages(&self, messages: Vec<Message>) -> Result<()> {
+        self.inner.add_messages(messages).await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.inner.clear().await
+    }
+
+    async fn replace_messages(&self, messages: Vec<Message>) -> Result<()> {
+        self.inner.replace_messages(messages).await
+    }
+}
+
+/// A [`Memory`] returning the most recent messages of an inner memory which fit in a token
+/// budget.
+pub struct TokenBudgetMemory<M> {
+    inner: M,
+    budget: usize,
+    keep_first: bool,
+    counter: Box<dyn TokenCounter>,
+}
+
+impl<M> TokenBudgetMemory<M> {
+    /// Returns the most recent messages of `inner` totalling at most `budget` tokens, as
+    /// estimated by [`ApproximateTokenCounter`].
+    pub fn new(inner: M, budget: usize) -> Self {
+        Self {
+            inner,
+            budget,
+            keep_first: false,
+            counter: Box::new(ApproximateTokenCounter),
+        }
+    }
+
+    /// Always returns the first system or user message, counting towards the budget.
+    pub fn with_keep_first(mut self, keep_first: bool) -> Self {
+        self.keep_first = keep_first;
+        self
+    }
+
+    /// Counts tokens with `counter`, typically backed by the tokenizer of the model.
+    pub fn with_token_counter<C>(mut self, counter: C) -> Self
+    where
+        C: TokenCounter + 'static,
+    {
+        self.counter = Box::new(counter);
+        self
+    }
+}
+
+#[async_trait]
+impl<M> Memory for TokenBudgetMemory<M>
+where
+    M: Memory,
+{
+    async fn messages(&self) -> Result<Vec<Message>> {
+        let mut total = 0;
+        Ok(recent_messages(
+            self.inner.messages().await?,
+            self.keep_first,
+            |message| {
+                total += self.counter.count(message);
+                total <= self.budget
+            },
+        ))
+    }
+
+    async fn add_messages(&self, messages: Vec<Message>) -> Result<()> {
+        self.inner.add_messages(messages).await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.inner.clear().await
+    }
+
+    async fn replace_messages(&self, messages: Vec<Message>) -> Result<()> {
+        self.inner.replace_messages(messages).await
+    }
+}
+
+/// Keeps the most recent messages accepted by `fits`, which is called from the newest message
+/// backwards until it refuses one.
+///
+/// The first system or user message is offered to `fits` first, and kept regardless of its
+/// answer, when `keep_first` is set. Tool results are never kept without the message holding
+/// their tool use.
+fn recent_messages<F>(mut messages: Vec<Message>, keep_first: bool, mut fits: F) -> Vec<Message>
+where
+    F: FnMut(&Message) -> bool,
+{
+    let first = messages
+        .iter()
+        .position(|message| matches!(message.role, Role::System | Role::User))
+        .filter(|_| keep_first);
+    if let Some(first) = first {
+        fits(&messages[first]);
+    }
+
+    let floor = first.map_or(0, |first| first + 1);
+    let mut start = messages.len();
+    while start > floor && fits(&messages[start - 1]) {
+        start -= 1;
+    }
+
+    while start < messages.len() && has_orphan_tool_result(&messages[start..]) {
+        start += 1;
+    }
+
+    let mut recent = messages.split_off(start);
+    if let Some(first) = first.filter(|first| *first < start) {
+        recent.insert(0, messages.swap_remove(first));
+    }
+    recent
+}
+
+/// Whether the first message holds a tool result whose tool use isn't part of `messages`.
+fn has_orphan_tool_result(messages: &[Message]) -> bool {
+    let Some(first) = messages.first() else {
+        return false;
+    };
+
+    first.content.iter().any(|content| match content {
+        Content::ToolResult(result) => !messages
+            .iter()
+            .flat_map(|message| message.tool_use())
+            .any(|tool_use| tool_use.id == result.id),
+        _ => false,
+    })
+}
+
+/// A [`Memory`] condensing older messages into a running summary, using a [`Completion`].
+///
+/// Once more than `threshold` messages are stored, every message but the `keep_recent` most
+/// recent ones is folded into a summary. The summary is stored as a system message in front of
+/// the recent messages, both persisted through the inner memory.
+pub struct SummarizingMemory<M> {
+    inner: M,
+    completion: Arc<dyn Completion>,
+    threshold: usize,
+    keep_recent: usize,
+    prompt: String,
+    lock: Mutex<()>,
+}
+
+impl<M> SummarizingMemory<M> {
+    pub fn new(inner: M, completion: Arc<dyn Completion>) -> Self {
+        Self {
+            inner,
+            completion,
+            threshold: 20,
+            keep_recent: 10,
+            prompt: DEFAULT_SUMMARY_PROMPT.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Sets the number of messages above which older messages are summarized, 20 by default.
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Sets the number of recent messages kept verbatim, 10 by default.
+    pub fn with_keep_recent(mut self, keep_recent: usize) -> Self {
+        self.keep_recent = keep_recent;
+        self
+    }
+
+    /// Replaces the instructions given to the model to write the summary.
+    pub fn with_prompt<S>(mut self, prompt: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.prompt = prompt.into();
+        self
+    }
+}
+
+const DEFAULT_SUMMARY_PROMPT: &str = "Progressively summarize the conversation below, adding to the previous summary. Keep every fact, decision and open question which may matter later on, and answer with the new summary only.";
+
+impl<M> SummarizingMemory<M>
+where
+    M: Memory,
+{
+    async fn summarize(&self, summary: Option<&Message>, messages: &[Message]) -> Result<Message> {
+        let transcript = messages
+            .iter()
+            .map(|message| format!("{}: {}", message.role, transcript_text(message)))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let request = Message {
+            role: Role::User,
+            content: vec![formatdoc! {"
+                {}
+
+                <summary>
+                {}
+                </summary>
+
+                <conversation>
+                {}
+                </conversation>
+            ", self.prompt, summary.map(transcript_text).unwrap_or_default(), transcript}
+            .into()],
+            ..Default::default()
+        };
+
+        let text = self
+            .completion
+            .i(vec![request])
+            .await?
+            .iter()
+            .map(transcript_text)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Ok(Message {
+            role: Role::System,
+            content: vec![text.trim().into()],
+            metadata: Some(json!({ "summary": true })),
+            ..Default::default()
+        })
+    }
+}
+
+#[async_trait]
+impl<M> Memory for SummarizingMemory<M>
+where
+    M: Memory,
+{
+    async fn messages(&self) -> Result<Vec<Message>> {
+        self.inner.messages().await
+    }
+
+    async fn add_messages(&self, messages: Vec<Message>) -> Result<()> {
+        let (summary, older) = {
+            let _guard = self.lock.lock().await;
+            self.inner.add_messages(messages).await?;
+
+            let mut messages = self.inner.messages().await?;
+            let summary = match messages.first() {
+                Some(message) if is_summary(message) => Some(messages.remove(0)),
+                _ => None,
+            };
+            if messages.len() <= self.threshold {
+                return Ok(());
+            }
+
+            // Tool results stay next to their tool use, so the split may keep more messages.
+            let mut split = messages.len().saturating_sub(self.keep_recent);
+            while split > 0 && has_orphan_tool_result(&messages[split..]) {
+                split -= 1;
+            }
+            if split == 0 {
+                return Ok(());
+            }
+
+            messages.truncate(split);
+            (summary, messages)
+        };
+
+        // The lock isn't held while summarizing, so that messages can be added meanwhile.
+        let new_summary = self.summarize(summary.as_ref(), &older).await?;
+
+        let _guard = self.lock.lock().await;
+        let mut messages = self.inner.messages().await?;
+        let summarized = summary.into_iter().chain(older).collect::<Vec<_>>();
+        // Another summary may have replaced these messages, or the memory been cleared.
+        if messages.len() < summarized.len()
+            || serde_json::to_value(&messages[..summarized.len()])?
+                != serde_json::to_value(&summarized)?
+        {
+            return Ok(());
+        }
+
+        let recent = messages.split_off(summarized.len());
+        self.inner
+            .replace_messages(std::iter::once(new_summary).chain(recent).collect())
+            .await
+    }
+
+    async fn clear(&self) -> Result<()> {
+        self.inner.clear().await
+    }
+
+    async fn replace_messages(&self, messages: Vec<Message>) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        self.inner.replace_messages(messages).await
+    }
+}
+
+fn is_summary(message: &Message) -> bool {
+    message
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get("summary"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+fn transcript_text(message: &Message) -> String {
+    message
+        .content
+        .iter()
+        .filter_map(|content| {
+            Some(match content {
+                Content::Text { text } => text.clone(),
+                Content::Image { .. } => "[image]".into(),
+                Content::Document {
+                    title: Some(title), ..
+                } => format!("[document: {}]", title),
+                Content::Document { .. } => "[document]".into(),
+                Content::Thinking { thinking, .. } => format!("[thinking: {}]", thinking),
+                Content::ToolUse(tool_use) => {
+                    format!("[called tool {} with {}]", tool_use.tool, tool_use.input)
+                }
+                Content::ToolResult(result) => format!("[tool result: {}]", result.text()),
+                Content::RedactedThinking { .. } => return None,
+            })
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A long-term [`Memory`] recalling the past messages relevant to the latest user message.
+///
+/// Every message added is stored in an inner memory and indexed into a [`VectorStore`], along
+/// with its session, role and creation time. [`Memory::messages`] returns the last `window`
+/// messages of the inner memory, preceded by a system message quoting up to `top_k` older
+/// messages of the session which are the most similar to the latest user message.
+///
+/// Only the text of messages is indexed. The vector store may be shared by many sessions, so
+/// [`Memory::clear`] deletes the documents this memory indexed, which are the only ones it can
+/// find since documents can't be listed through a [`VectorStore`].
+pub struct VectorStoreMemory<M> {
+    inner: M,
+    vector_store: Arc<dyn VectorStore>,
+    indexed: Mutex<Vec<String>>,
+    session: String,
+    window: usize,
+    top_k: usize,
+    min_score: Option<f32>,
+}
+
+impl<M> VectorStoreMemory<M> {
+    pub fn new(inner: M, vector_store: Arc<dyn VectorStore>) -> Self {
+        Self {
+            inner,
+            vector_store,
+            indexed: Mutex::new(vec![]),
+            session: "default".into(),
+            window: 10,
+            top_k: 4,
+            min_score: None,
+        }
+    }
+
+    /// Sets the session of the messages, `default` by default.
+    pub fn with_session<S>(mut self, session: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.session = session.into();
+        self
+    }
+
+    /// Sets the number of recent messages returned verbatim, 10 by default.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the maximum number of older messages recalled, 4 by default.
+    pub fn with_top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Ignores the older messages whose similarity score is below `min_score`.
+    pub fn with_min_score(mut self, min_score: f32) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+}
+
+/// How many more documents than needed are searched, since the documents of other sessions
+/// and of the recent window are filtered out of the results.
+const RECALL_OVERSAMPLING: usize = 4;
+
+impl<M> VectorStoreMemory<M>
+where
+    M: Memory,
+{
+    async fn recall(&self, recent: &[Message]) -> Result<Option<Message>> {
+        let query = recent
+            .iter()
+            .rev()
+            .filter(|message| message.role == Role::User)
+            .map(message_text)
+            .find(|text| !text.trim().is_empty());
+        let Some(query) = query else {
+            return Ok(None);
+        };
+        if self.top_k == 0 {
+            return Ok(None);
+        }
+
+        let limit = (self.top_k + self.window) * RECALL_OVERSAMPLING;
+        let mut recalled = self
+            .vector_store
+            .search(&query, limit as u64)
+            .await?
+            .into_iter()
+            .filter(|similarity| self.min_score.is_none_or(|min| similarity.score >= min))
+            .map(|similarity| similarity.stored.document)
+            .filter(|document| {
+                document.metadata.get("session").and_then(Value::as_str)
+                    == Some(self.session.as_str())
+            })
+            .filter(|document| {
+                let role = document.metadata.get("role").and_then(Value::as_str);
+                !recent.iter().any(|message| {
+                    role == Some(message.role.as_str()) && message_text(message) == document.content
+                })
+            })
+            .take(self.top_k)
+            .collect::<Vec<_>>();
+        if recalled.is_empty() {
+            return Ok(None);
+        }
+
+        recalled.sort_by_key(|document| {
+            document
+                .metadata
+                .get("created_at")
+                .and_then(Value::as_u64)
+                .unwrap_or_default()
+        });
+        let transcript = recalled
+            .iter()
+            .map(|document| {
+                let role = document
+                    .metadata
+                    .get("role")
+                    .and_then(Value::as_str)
+                    .unwrap_or("unknown");
+                format!("{}: {}", role, document.content)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Ok(Some(Message {
+            role: Role::System,
+            content: vec![formatdoc! {"
+                Relevant messages from earlier in the conversation:
+
+                {}
+            ", transcript}
+            .trim_end()
+            .into()],
+            metadata: Some(json!({ "recalled": true })),
+            ..Default::default()
+        }))
+    }
+}
+
+#[async_trait]
+impl<M> Memory for VectorStoreMemory<M>
+where
+    M: Memory,
+{
+    async fn messages(&self) -> Result<Vec<Message>> {
+        let mut count = 0;
+        let recent = recent_messages(self.inner.messages().await?, false, |_| {
+            count += 1;
+            count <= self.window
+        });
+
+        Ok(self
+            .recall(&recent)
+            .await?
+            .into_iter()
+            .chain(recent)
+            .collect())
+    }
+
+    async fn add_messages(&self, mut messages: Vec<Message>) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        for message in &mut messages {
+            message.created_at.get_or_insert(now);
+        }
+
+        let documents = messages
+            .iter()
+            .map(|message| (message, message_text(message)))
+            .filter(|(_, text)| !text.trim().is_empty())
+            .map(|(message, text)| Document {
+                content: text,
+                metadata: HashMap::from([
+                    ("session".into(), json!(self.session)),
+                    ("role".into(), json!(message.role)),
+                    ("created_at".into(), json!(message.created_at)),
+                ]),
+            })
+            .collect::<Vec<_>>();
+
+        // The messages are stored first, so that they are never recalled if storing them fails.
+        self.inner.add_messages(messages).await?;
+        if !documents.is_empty() {
+            let ids = self.vector_store.add_documents(&documents).await?;
+            self.indexed.lock().await.extend(ids);
+        }
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let mut indexed = self.indexed.lock().await;
+        if !indexed.is_empty() {
+            self.vector_store.delete_documents(&indexed).await?;
+            indexed.clear();
+        }
+        drop(indexed);
+
+        self.inner.clear().await
+    }
+
+    /// Replaces the messages of the inner memory, leaving those indexed for recall.
+    async fn replace_messages(&self, messages: Vec<Message>) -> Result<()> {
+        self.inner.replace_messages(messages).await
+    }
+}
+
+/// The text content of a message, ignoring images and tools.
+fn message_text(message: &Message) -> String {
+    message
+        .content
+        .iter()
+        .filter_map(|content| match content {
+            Content::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{stream, StreamExt};
+
+    use super::*;
+    use crate::{
+        completion::{CompletionResponse, StreamEvent, StreamEventEnvelope},
+        document::StoredDocument,
+        message::{ToolResult, ToolUse},
+        vector_store::Similarity,
+    };
+
+    #[derive(Default)]
+    struct VecMemory(Mutex<Vec<Message>>);
+
+    #[async_trait]
+    impl Memory for VecMemory {
+        async fn messages(&self) -> Result<Vec<Message>> {
+            Ok(self.0.lock().await.clone())
+        }
+
+        async fn add_messages(&self, messages: Vec<Message>) -> Result<()> {
+            self.0.lock().await.extend(messages);
+            Ok(())
+        }
+
+        async fn clear(&self) -> Result<()> {
+            self.0.lock().await.clear();
+            Ok(())
+        }
+
+        async fn replace_messages(&self, messages: Vec<Message>) -> Result<()> {
+            *self.0.lock().await = messages;
+            Ok(())
+        }
+    }
+
+    fn message(role: Role, content: Content) -> Message {
+        Message {
+            role,
+            content: vec![content],
+            ..Default::default()
+        }
+    }
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            message(Role::System, "Be helpful".into()),
+            message(Role::User, "What's the weather?".into()),
+            message(
+                Role::Assistant,
+                Content::ToolUse(ToolUse {
+                    id: "1".into(),
+                    tool: "forecast".into(),
+                    input: json!({}),
+                }),
+            ),
+            message(
+                Role::User,
+                Content::ToolResult(ToolResult::new("1", vec!["Sunny".into()])),
+            ),
+            message(Role::Assistant, "It's sunny".into()),
+        ]
+    }
+
+    fn texts(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|message| match &message.content[0] {
+                Content::Text { text } => text.clone(),
+                Content::ToolUse(tool_use) => format!("use {}", tool_use.id),
+                Content::ToolResult(result) => format!("result {}", result.id),
+                content => panic!("unexpected content: {:?}", content),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_window_memory() {
+        let memory = WindowMemory::new(VecMemory::default(), 2);
+        memory.add_messages(conversation()).await.unwrap();
+
+        // The tool result can't be kept without its tool use.
+        let messages = memory.messages().await.unwrap();
+        assert_eq!(texts(&messages), ["It's sunny"]);
+
+        let memory = WindowMemory::new(memory.inner, 4).with_keep_first(true);
+        let messages = memory.messages().await.unwrap();
+        assert_eq!(
+            texts(&messages),
+            ["Be helpful", "use 1", "result 1", "It's sunny"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_memory() {
+        let memory = TokenBudgetMemory::new(VecMemory::default(), 3)
+            .with_token_counter(|_: &Message| 1)
+            .with_keep_first(true);
+        memory.add_messages(conversation()).await.unwrap();
+
+        let messages = memory.messages().await.unwrap();
+        assert_eq!(texts(&messages), ["Be helpful", "It's sunny"]);
+
+        let memory = TokenBudgetMemory::new(memory.inner, 1000);
+        assert_eq!(memory.messages().await.unwrap().len(), 5);
+    }
+
+    /// Answers with the number of lines of the conversation to summarize.
+    struct LineCounter;
+
+    #[async_trait]
+    impl Completion for LineCounter {
+        async fn complete(&self, messages: Vec<Message>) -> Result<CompletionResponse> {
+            let text = transcript_text(&messages[0]);
+            let start = text.find("<conversation>").unwrap();
+            let end = text.find("</conversation>").unwrap();
+            let lines = text[start..end].lines().count() - 1;
+
+            let events = vec![
+                StreamEventEnvelope {
+                    index: 0,
+                    event: StreamEvent::Start {
+                        index: 0,
+                        model: "counter".into(),
+                        role: Role::Assistant,
+                        inner: vec![],
+                    },
+                },
+                StreamEventEnvelope {
+                    index: 0,
+                    event: StreamEvent::Delta {
+                        index: 0,
+                        inner: vec![format!("{} lines", lines).into()],
+                    },
+                },
+            ];
+
+            Ok(stream::iter(events.into_iter().map(Ok)).boxed().into())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summarizing_memory() {
+        let memory = SummarizingMemory::new(VecMemory::default(), Arc::new(LineCounter))
+            .with_threshold(3)
+            .with_keep_recent(2);
+
+        memory
+            .add_messages(conversation()[..3].to_vec())
+            .await
+            .unwrap();
+        assert_eq!(memory.messages().await.unwrap().len(), 3);
+
+        // The tool use is kept next to its result, so only two messages are summarized.
+        memory
+            .add_messages(conversation()[3..].to_vec())
+            .await
+            .unwrap();
+        let messages = memory.messages().await.unwrap();
+        assert!(is_summary(&messages[0]));
+        assert_eq!(
+            texts(&messages),
+            ["2 lines", "use 1", "result 1", "It's sunny"]
+        );
+
+        memory
+            .add_messages(vec![message(Role::User, "Thanks!".into())])
+            .await
+            .unwrap();
+        let messages = memory.messages().await.unwrap();
+        assert_eq!(texts(&messages), ["2 lines", "It's sunny", "Thanks!"]);
+    }
+
+    /// Counts lines like [`LineCounter`], pausing the first summary until `resume` is
+    /// notified, after announcing it on `started`.
+    #[derive(Default)]
+    struct PausedLineCounter {
+        paused: std::sync::atomic::AtomicBool,
+        started: tokio::sync::Notify,
+        resume: tokio::sync::Notify,
+    }
+
+    #[async_trait]
+    impl Completion for PausedLineCounter {
+        async fn complete(&self, messages: Vec<Message>) -> Result<CompletionResponse> {
+            if !self.paused.swap(true, std::sync::atomic::Ordering::SeqCst) {
+                self.started.notify_one();
+                self.resume.notified().await;
+            }
+            LineCounter.complete(messages).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summarizing_memory_concurrent_adds() {
+        let completion = Arc::new(PausedLineCounter::default());
+        let memory = SummarizingMemory::new(VecMemory::default(), completion.clone())
+            .with_threshold(3)
+            .with_keep_recent(2);
+        memory
+            .add_messages(conversation()[..3].to_vec())
+            .await
+            .unwrap();
+
+        // Messages are added while the first summary is written, and summarized first.
+        let (paused, concurrent) =
+            tokio::join!(memory.add_messages(conversation()[3..].to_vec()), async {
+                completion.started.notified().await;
+                let added = tokio::time::timeout(
+                    std::time::Duration::from_secs(5),
+                    memory.add_messages(vec![message(Role::User, "Thanks!".into())]),
+                )
+                .await;
+                completion.resume.notify_one();
+                added
+            });
+        paused.unwrap();
+        concurrent.unwrap().unwrap();
+
+        // The first summary is dropped, since the messages it covers were summarized.
+        let messages = memory.messages().await.unwrap();
+        assert_eq!(texts(&messages), ["4 lines", "It's sunny", "Thanks!"]);
+    }
+
+    /// Scores documents by the number of words they share with the query.
+    #[derive(Default)]
+    struct WordStore(Mutex<Vec<Option<Document>>>);
+
+    #[async_trait]
+    impl VectorStore for WordStore {
+        async fn ensure_index(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn add_documents(&self, documents: &[Document]) -> Result<Vec<String>> {
+            let mut stored = self.0.lock().await;
+            let ids = (stored.len()..stored.len() + documents.len())
+                .map(|id| id.to_string())
+                .collect();
+            stored.extend(documents.iter().cloned().map(Some));
+            Ok(ids)
+        }
+
+        async fn delete_documents(&self, ids: &[String]) -> Result<()> {
+            let mut stored = self.0.lock().await;
+            for id in ids {
+                if let Some(document) = stored.get_mut(id.parse::<usize>()?) {
+                    *document = None;
+                }
+            }
+            Ok(())
+        }
+
+        async fn get_documents(&self, _ids: &[String]) -> Result<Vec<StoredDocument>> {
+            anyhow::bail!("unsupported")
+        }
+
+        async fn search(&self, query: &str, limit: u64) -> Result<Vec<Similarity>> {
+            let words = |text: &str| {
+                text.to_lowercase()
+                    .split(|char: char| !char.is_alphanumeric())
+                    .filter(|word| word.len() > 3)
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            };
+            let query = words(query);
+
+            let mut similarities = self
+                .0
+                .lock()
+                .await
+                .iter()
+                .enumerate()
+                .filter_map(|(id, document)| Some((id, document.as_ref()?)))
+                .map(|(id, document)| Similarity {
+                    score: words(&document.content)
+                        .iter()
+                        .filter(|word| query.contains(word))
+                        .count() as f32,
+                    stored: StoredDocument {
+                        id: id.to_string(),
+                        document: document.clone(),
+                    },
+                })
+                .collect::<Vec<_>>();
+            similarities.sort_by(|a, b| b.score.total_cmp(&a.score));
+            similarities.truncate(limit as usize);
+            Ok(similarities)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_vector_store_memory() {
+        let store = Arc::new(WordStore::default());
+        let memory = VectorStoreMemory::new(VecMemory::default(), store.clone())
+            .with_session("a")
+            .with_window(2)
+            .with_top_k(1)
+            .with_min_score(1.0);
+        let other = VectorStoreMemory::new(VecMemory::default(), store).with_session("b");
+
+        other
+            .add_messages(vec![message(
+                Role::User,
+                "My favourite colour is green".into(),
+            )])
+            .await
+            .unwrap();
+        memory
+            .add_messages(vec![
+                message(Role::User, "My favourite colour is blue".into()),
+                message(Role::Assistant, "Noted!".into()),
+                message(Role::User, "Book a table for tonight".into()),
+                message(Role::Assistant, "Done.".into()),
+            ])
+            .await
+            .unwrap();
+
+        // Nothing older is relevant to the latest user message.
+        let messages = memory.messages().await.unwrap();
+        assert_eq!(texts(&messages), ["Book a table for tonight", "Done."]);
+        assert!(messages.iter().all(|message| message.created_at.is_some()));
+
+        memory
+            .add_messages(vec![message(Role::User, "Which colour do I like?".into())])
+            .await
+            .unwrap();
+        let messages = memory.messages().await.unwrap();
+        assert_eq!(
+            texts(&messages),
+            [
+                "Relevant messages from earlier in the conversation:\n\nuser: My favourite colour is blue",
+                "Done.",
+                "Which colour do I like?",
+            ]
+        );
+        assert_eq!(messages[0].metadata, Some(json!({ "recalled": true })));
+    }
+
+    #[tokio::test]
+    async fn test_vector_store_memory_clear() {
+        let store = Arc::new(WordStore::default());
+        let memory = VectorStoreMemory::new(VecMemory::default(), store.clone())
+            .with_window(1)
+            .with_min_score(1.0);
+        let other = VectorStoreMemory::new(VecMemory::default(), store.clone()).with_session("b");
+
+        other
+            .add_messages(vec![message(
+                Role::User,
+                "My favourite colour is green".into(),
+            )])
+            .await
+            .unwrap();
+        memory
+            .add_messages(vec![
+                message(Role::User, "My favourite colour is blue".into()),
+                message(Role::Assistant, "Noted!".into()),
+            ])
+            .await
+            .unwrap();
+        memory.clear().await.unwrap();
+        assert!(memory.messages().await.unwrap().is_empty());
+
+        // Only the documents of the cleared session are deleted.
+        memory
+            .add_messages(vec![message(Role::User, "Which colour do I like?".into())])
+            .await
+            .unwrap();
+        let messages = memory.messages().await.unwrap();
+        assert_eq!(texts(&messages), ["Which colour do I like?"]);
+        assert_eq!(store.search("colour", 10).await.unwrap().len(), 2);
+    }
 }
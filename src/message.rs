@@ -1,16 +1,54 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use serde_json::Value;
 
+/// The version of the serialized shape of [`Message`].
+///
+/// Memories persist it along with stored messages, so that messages written with an older
+/// shape can be upgraded by [`Message::from_stored`] when they are read back.
+///
+/// - Version 1 had free-form `role` strings.
+/// - Version 2 restricts them to the [`Role`]s.
+pub const MESSAGE_VERSION: u32 = 2;
+
 #[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
 pub struct Message {
-    pub role: String,
+    pub role: Role,
     pub content: Vec<Content>,
     pub metadata: Option<Value>,
     pub name: Option<String>,
     pub id: Option<String>,
     pub created_at: Option<u64>,
+    /// Marks the end of a prompt prefix to cache after this message, for providers supporting
+    /// prompt caching, which others ignore.
+    ///
+    /// It only applies to the request the message is sent in, so it isn't serialized and
+    /// memories never store it.
+    #[serde(skip)]
+    pub cache_breakpoint: bool,
 }
 
 impl Message {
+    /// Deserializes a message stored with the shape of `version`.
+    pub fn from_stored(version: u32, mut value: Value) -> Result<Message> {
+        match version {
+            1 => {
+                if let Some(role) = value.get_mut("role") {
+                    *role = upgrade_role(role)?;
+                }
+                Ok(serde_json::from_value(value)?)
+            }
+            2 => Ok(serde_json::from_value(value)?),
+            version => Err(anyhow!(
+                "unsupported message version {}, the latest is {}",
+                version,
+                MESSAGE_VERSION
+            )),
+        }
+    }
+
     pub fn tool_use(&self) -> impl Iterator<Item = &ToolUse> + '_ {
         self.content.iter().filter_map(|content| match content {
             Content::ToolUse(tool_use) => Some(tool_use),
@@ -19,6 +57,72 @@ impl Message {
     }
 }
 
+/// The author of a [`Message`].
+///
+/// Roles are serialized in lowercase, as `role` strings always were, so previously stored
+/// messages can still be read.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Instructions framing the conversation, which most providers take apart from the
+    /// messages.
+    System,
+    #[default]
+    User,
+    Assistant,
+    /// The results of tool calls, for providers which don't send them as user messages.
+    Tool,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Maps a version 1 `role` string to the matching [`Role`], rejecting the roles which have
+/// none.
+fn upgrade_role(role: &Value) -> Result<Value> {
+    let name = role
+        .as_str()
+        .ok_or_else(|| anyhow!("invalid role {}", role))?;
+    let role = match name.to_lowercase().as_str() {
+        "system" | "developer" => Role::System,
+        "user" | "human" => Role::User,
+        "assistant" | "ai" | "model" => Role::Assistant,
+        "tool" | "function" => Role::Tool,
+        _ => return Err(anyhow!("unknown role `{}` in a version 1 message", name)),
+    };
+    Ok(Value::String(role.as_str().into()))
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(role: &str) -> Result<Self> {
+        match role {
+            "system" => Ok(Role::System),
+            "user" => Ok(Role::User),
+            "assistant" => Ok(Role::Assistant),
+            "tool" => Ok(Role::Tool),
+            role => Err(anyhow!("unknown role `{}`", role)),
+        }
+    }
+}
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct ToolUse {
     pub id: String,
@@ -29,23 +133,164 @@ pub struct ToolUse {
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct ToolResult {
     pub id: String,
-    pub content: String,
+    #[serde(deserialize_with = "deserialize_tool_result_content")]
+    pub content: Vec<Content>,
+    /// Whether the tool failed, in which case `content` describes the error.
+    #[serde(default)]
+    pub is_error: bool,
+}
+
+impl ToolResult {
+    pub fn new<S>(id: S, content: Vec<Content>) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            id: id.into(),
+            content,
+            is_error: false,
+        }
+    }
+
+    pub fn error<S, E>(id: S, error: E) -> Self
+    where
+        S: Into<String>,
+        E: AsRef<str>,
+    {
+        Self {
+            id: id.into(),
+            content: vec![error.into()],
+            is_error: true,
+        }
+    }
+
+    /// The concatenation of the text blocks of the result.
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|content| match content {
+                Content::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Tool results used to be plain strings; accept them so previously stored messages can still
+/// be read.
+fn deserialize_tool_result_content<'de, D>(deserializer: D) -> Result<Vec<Content>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum ToolResultContent {
+        Text(String),
+        Blocks(Vec<Content>),
+    }
+
+    Ok(
+        match <ToolResultContent as serde::Deserialize>::deserialize(deserializer)? {
+            ToolResultContent::Text(text) => vec![text.into()],
+            ToolResultContent::Blocks(blocks) => blocks,
+        },
+    )
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Content {
-    Text { text: String },
-    Image { source: ImageSource },
+    Text {
+        text: String,
+    },
+    Image {
+        source: ImageSource,
+    },
+    Document {
+        source: DocumentSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+    },
+    /// The reasoning of the model before its answer.
+    Thinking {
+        thinking: String,
+        /// The signature some providers require to receive the thinking back, unaltered.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
+    },
+    /// Reasoning the provider encrypted for safety reasons, which must be sent back unaltered.
+    RedactedThinking {
+        data: String,
+    },
     ToolUse(ToolUse),
     ToolResult(ToolResult),
 }
 
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DocumentSource {
+    /// A base64 encoded PDF file.
+    Pdf { data: String },
+    /// A plain text document.
+    Text { text: String },
+}
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ImageSource {
-    Base64 { data: String },
-    Url { url: String },
+    Base64 {
+        data: String,
+        /// The media type of the image, such as `image/png`, detected from the data when not
+        /// set.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        media_type: Option<String>,
+    },
+    Url {
+        url: String,
+        /// The media type of the image, when known ahead of fetching it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        media_type: Option<String>,
+    },
+}
+
+impl ImageSource {
+    /// The media type of the image, either set or detected from the magic bytes of base64 data.
+    pub fn media_type(&self) -> Option<String> {
+        match self {
+            ImageSource::Base64 {
+                media_type: Some(media_type),
+                ..
+            }
+            | ImageSource::Url {
+                media_type: Some(media_type),
+                ..
+            } => Some(media_type.clone()),
+            ImageSource::Base64 { data, .. } => {
+                detect_image_media_type(&decode_base64_prefix(data, 12)).map(Into::into)
+            }
+            ImageSource::Url { .. } => None,
+        }
+    }
+}
+
+/// Detects the media type of an image out of its first bytes, for the PNG, JPEG, GIF and WebP
+/// formats.
+pub fn detect_image_media_type(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Decodes the first `len` bytes of standard base64 data, or nothing if it is invalid.
+fn decode_base64_prefix(data: &str, len: usize) -> Vec<u8> {
+    let prefix = data.get(..len.div_ceil(3) * 4).unwrap_or(data);
+    let mut bytes = BASE64.decode(prefix).unwrap_or_default();
+    bytes.truncate(len);
+    bytes
 }
 
 impl<S> From<S> for Content
@@ -58,3 +303,83 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_stored_roles() {
+        let message =
+            Message::from_stored(1, json!({ "role": "assistant", "content": [] })).unwrap();
+        assert_eq!(message.role, Role::Assistant);
+        assert_eq!(serde_json::to_value(message).unwrap()["role"], "assistant");
+
+        assert!(Message::from_stored(1, json!({ "role": "narrator", "content": [] })).is_err());
+        assert_eq!("tool".parse::<Role>().unwrap(), Role::Tool);
+
+        // Version 1 roles are mapped to the closest role, which version 2 requires.
+        for (stored, role) in [("human", Role::User), ("function", Role::Tool)] {
+            let message = json!({ "role": stored, "content": [] });
+            assert_eq!(Message::from_stored(1, message.clone()).unwrap().role, role);
+            assert!(Message::from_stored(MESSAGE_VERSION, message).is_err());
+        }
+    }
+
+    #[test]
+    fn test_content_serialization() {
+        let content: Vec<Content> = serde_json::from_value(json!([
+            { "type": "document", "source": { "type": "pdf", "data": "JVBERi0=" } },
+            { "type": "thinking", "thinking": "Hmm", "signature": "abc" },
+        ]))
+        .unwrap();
+        assert!(matches!(
+            &content[0],
+            Content::Document {
+                source: DocumentSource::Pdf { .. },
+                title: None
+            }
+        ));
+        assert_eq!(
+            serde_json::to_value(&content[1]).unwrap(),
+            json!({ "type": "thinking", "thinking": "Hmm", "signature": "abc" })
+        );
+    }
+
+    #[test]
+    fn test_image_media_type() {
+        let image = |data: &str| ImageSource::Base64 {
+            data: data.into(),
+            media_type: None,
+        };
+
+        assert_eq!(
+            image("iVBORw0KGgoAAAANSUhEUgAAAAEAAAAB").media_type(),
+            Some("image/png".into())
+        );
+        assert_eq!(
+            image("/9j/4AAQSkZJRgABAQ").media_type(),
+            Some("image/jpeg".into())
+        );
+        assert_eq!(
+            image("R0lGODlhAQABAIAAAP").media_type(),
+            Some("image/gif".into())
+        );
+        assert_eq!(
+            image("UklGRiQAAABXRUJQVlA4IBgAAAAw").media_type(),
+            Some("image/webp".into())
+        );
+        assert_eq!(image("aGVsbG8gd29ybGQ=").media_type(), None);
+
+        let declared: ImageSource =
+            serde_json::from_value(json!({ "type": "url", "url": "https://example.com/a.jpg", "media_type": "image/jpeg" }))
+                .unwrap();
+        assert_eq!(declared.media_type(), Some("image/jpeg".into()));
+        // Images stored before media types existed can still be read.
+        let stored: ImageSource =
+            serde_json::from_value(json!({ "type": "base64", "data": "" })).unwrap();
+        assert_eq!(stored.media_type(), None);
+    }
+}
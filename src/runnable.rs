@@ -0,0 +1,247 @@
+use std::{future::Future, marker::PhantomData, ops::BitOr, sync::Arc};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::chain::Chain;
+
+/// A typed unit of work turning an `I` into an `O`.
+///
+/// Unlike [`Chain`], which works on untyped [`Value`]s, runnables are checked at compile time:
+/// two runnables can only be piped together when the output of the first one is the input of
+/// the second one.
+#[async_trait]
+pub trait Runnable<I, O>: Send + Sync
+where
+    I: Send + 'static,
+    O: Send + 'static,
+{
+    async fn invoke(&self, input: I) -> Result<O>;
+
+    /// Invokes the runnable on every input, running at most `concurrency` invocations at once.
+    ///
+    /// Outputs are returned in the same order as the inputs.
+    async fn batch(&self, inputs: Vec<I>, concurrency: usize) -> Result<Vec<O>> {
+        stream::iter(inputs)
+            .map(|input| self.invoke(input))
+            .buffered(concurrency.max(1))
+            .try_collect()
+            .await
+    }
+}
+
+pub trait RunnableExt<I, O>: Runnable<I, O> + Sized
+where
+    I: Send + 'static,
+    O: Send + 'static,
+{
+    /// Feeds the output of this runnable into `next`.
+    fn pipe<N, P>(self, next: N) -> Pipe<Self, N, O>
+    where
+        N: Runnable<O, P>,
+        P: Send + 'static,
+    {
+        Pipe {
+            current: self,
+            next,
+            _marker: PhantomData,
+        }
+    }
+
+    fn boxed(self) -> BoxRunnable<I, O>
+    where
+        Self: 'static,
+    {
+        BoxRunnable(Arc::new(self))
+    }
+}
+
+impl<R, I, O> RunnableExt<I, O> for R
+where
+    R: Runnable<I, O>,
+    I: Send + 'static,
+    O: Send + 'static,
+{
+}
+
+/// Two runnables executed one after the other, see [`RunnableExt::pipe`].
+pub struct Pipe<A, B, M> {
+    current: A,
+    next: B,
+    _marker: PhantomData<fn() -> M>,
+}
+
+#[async_trait]
+impl<A, B, I, M, O> Runnable<I, O> for Pipe<A, B, M>
+where
+    A: Runnable<I, M>,
+    B: Runnable<M, O>,
+    I: Send + 'static,
+    M: Send + 'static,
+    O: Send + 'static,
+{
+    async fn invoke(&self, input: I) -> Result<O> {
+        let output = self.current.invoke(input).await?;
+        self.next.invoke(output).await
+    }
+}
+
+/// A type-erased, cheaply cloneable [`Runnable`].
+///
+/// Boxed runnables can be composed with the `|` operator, mirroring `Box<dyn Chain>`.
+pub struct BoxRunnable<I, O>(Arc<dyn Runnable<I, O>>);
+
+impl<I, O> Clone for BoxRunnable<I, O> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+#[async_trait]
+impl<I, O> Runnable<I, O> for BoxRunnable<I, O>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+{
+    async fn invoke(&self, input: I) -> Result<O> {
+        self.0.invoke(input).await
+    }
+}
+
+impl<I, M, O> BitOr<BoxRunnable<M, O>> for BoxRunnable<I, M>
+where
+    I: Send + 'static,
+    M: Send + 'static,
+    O: Send + 'static,
+{
+    type Output = BoxRunnable<I, O>;
+
+    fn bitor(self, rhs: BoxRunnable<M, O>) -> Self::Output {
+        self.pipe(rhs).boxed()
+    }
+}
+
+/// A [`Runnable`] backed by an async function, see [`from_fn`].
+pub struct RunnableFn<F>(F);
+
+/// Creates a [`Runnable`] from an async function or closure.
+pub fn from_fn<F>(f: F) -> RunnableFn<F> {
+    RunnableFn(f)
+}
+
+#[async_trait]
+impl<F, Fut, I, O> Runnable<I, O> for RunnableFn<F>
+where
+    F: Fn(I) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<O>> + Send,
+    I: Send + 'static,
+    O: Send + 'static,
+{
+    async fn invoke(&self, input: I) -> Result<O> {
+        (self.0)(input).await
+    }
+}
+
+/// Any dynamic [`Chain`] can be used as a typed runnable, as long as its input can be
+/// serialized from `I` and its output deserialized into `O`.
+#[async_trait]
+impl<I, O> Runnable<I, O> for Box<dyn Chain>
+where
+    I: Serialize + Send + 'static,
+    O: DeserializeOwned + Send + 'static,
+{
+    async fn invoke(&self, input: I) -> Result<O> {
+        let output = self.run(serde_json::to_value(input)?).await?;
+        Ok(serde_json::from_value(output)?)
+    }
+}
+
+impl<I, O> From<Box<dyn Chain>> for BoxRunnable<I, O>
+where
+    I: Serialize + Send + 'static,
+    O: DeserializeOwned + Send + 'static,
+{
+    fn from(chain: Box<dyn Chain>) -> Self {
+        chain.boxed()
+    }
+}
+
+struct RunnableChain<I, O> {
+    runnable: BoxRunnable<I, O>,
+}
+
+#[async_trait]
+impl<I, O> Chain for RunnableChain<I, O>
+where
+    I: DeserializeOwned + Send + 'static,
+    O: Serialize + Send + 'static,
+{
+    async fn run(&self, input: Value) -> Result<Value> {
+        let output = self.runnable.invoke(serde_json::from_value(input)?).await?;
+        Ok(serde_json::to_value(output)?)
+    }
+}
+
+impl<I, O> From<BoxRunnable<I, O>> for Box<dyn Chain>
+where
+    I: DeserializeOwned + Send + 'static,
+    O: Serialize + Send + 'static,
+{
+    fn from(runnable: BoxRunnable<I, O>) -> Self {
+        Box::new(RunnableChain { runnable })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pipe_and_chain_conversion() {
+        let length = from_fn(|text: String| async move { Ok(text.len()) }).boxed();
+        let double = from_fn(|n: usize| async move { Ok(n * 2) }).boxed();
+
+        let runnable = length | double;
+        assert_eq!(runnable.invoke("four".to_string()).await.unwrap(), 8);
+
+        let chain: Box<dyn Chain> = runnable.into();
+        assert_eq!(chain.run(json!("three")).await.unwrap(), json!(10));
+
+        let back: BoxRunnable<String, usize> = chain.into();
+        assert_eq!(back.invoke("two".to_string()).await.unwrap(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_batch_respects_concurrency() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let runnable = from_fn({
+            let running = running.clone();
+            let peak = peak.clone();
+            move |n: u64| {
+                let running = running.clone();
+                let peak = peak.clone();
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(n + 1)
+                }
+            }
+        });
+
+        let outputs = runnable.batch((0..10).collect(), 3).await.unwrap();
+
+        assert_eq!(outputs, (1..11).collect::<Vec<u64>>());
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+    }
+}
@@ -1,13 +1,26 @@
-use std::{collections::HashMap, hash::Hash, sync::Arc};
+use std::{
+    collections::HashMap, future::Future, hash::Hash, marker::PhantomData, sync::Arc,
+    time::Duration,
+};
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
-use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use futures::future::join_all;
+use jsonschema::Validator;
+use schemars::{
+    schema::{InstanceType, RootSchema, SingleOrVec},
+    schema_for, JsonSchema,
+};
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
+use tokio::sync::Semaphore;
 
-use crate::message::{ToolResult, ToolUse};
+use crate::{
+    completion::{ToolChoice, ToolSelection},
+    message::{Content, Message, Role, ToolResult, ToolUse},
+};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ToolDescriptor {
     pub name: String,
     pub description: String,
@@ -25,33 +38,304 @@ pub trait Tool {
 
     fn description(&self) -> String;
 
+    /// Whether the tool is executed by the application rather than by the [`ToolProvider`],
+    /// see [`ExternalTool`].
+    fn external(&self) -> bool {
+        false
+    }
+
     fn schema(&self) -> ToolDescriptor {
         ToolDescriptor {
             name: self.name().to_string(),
             description: self.description().to_string(),
             input: schema_for!(Self::Input),
             output: schema_for!(Self::Output),
-            external: false,
+            external: self.external(),
         }
     }
 
     async fn execute(&self, input: Value) -> Result<String>;
+
+    /// Executes the tool, returning rich content such as images.
+    ///
+    /// Defaults to a single text block holding the output of [`Tool::execute`].
+    async fn execute_content(&self, input: Value) -> Result<Vec<Content>>
+    where
+        Self: Sync,
+    {
+        Ok(vec![self.execute(input).await?.into()])
+    }
+}
+
+/// Deserializes `input`, runs `f` on it and serializes its output as a tool result.
+///
+/// String outputs are returned as they are, while any other output is serialized as JSON.
+pub async fn execute_typed<I, O, F, Fut>(input: Value, f: F) -> Result<String>
+where
+    I: DeserializeOwned,
+    O: Serialize,
+    F: FnOnce(I) -> Fut,
+    Fut: Future<Output = Result<O>>,
+{
+    let output = f(serde_json::from_value(input)?).await?;
+    match serde_json::to_value(output)? {
+        Value::String(output) => Ok(output),
+        output => Ok(serde_json::to_string(&output)?),
+    }
+}
+
+/// A [`Tool`] backed by an async function or closure.
+///
+/// ```ignore
+/// provider.register(FnTool::new(
+///     "add",
+///     "Adds two numbers",
+///     |input: AddInput| async move { Ok(input.a + input.b) },
+/// ))?;
+/// ```
+pub struct FnTool<I, O, F> {
+    name: String,
+    description: String,
+    f: F,
+    _marker: PhantomData<fn(I) -> O>,
+}
+
+impl<I, O, F> FnTool<I, O, F> {
+    pub fn new<N, D>(name: N, description: D, f: F) -> Self
+    where
+        N: Into<String>,
+        D: Into<String>,
+    {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            f,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<I, O, F, Fut> Tool for FnTool<I, O, F>
+where
+    I: JsonSchema + DeserializeOwned + Send,
+    O: JsonSchema + Serialize,
+    F: Fn(I) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<O>> + Send,
+{
+    type Input = I;
+    type Output = O;
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    async fn execute(&self, input: Value) -> Result<String> {
+        execute_typed(input, &self.f).await
+    }
+}
+
+/// A tool advertised to the model but executed outside of the [`ToolProvider`], for example in
+/// a browser or by asking a human.
+///
+/// Calls to external tools suspend the execution of [`ToolProvider::execute_all`], which
+/// returns the pending [`ToolUse`]s to the application. Their results are then submitted with
+/// [`ToolExecution::submit`].
+pub struct ExternalTool<I, O> {
+    name: String,
+    description: String,
+    _marker: PhantomData<fn(I) -> O>,
+}
+
+impl<I, O> ExternalTool<I, O> {
+    pub fn new<N, D>(name: N, description: D) -> Self
+    where
+        N: Into<String>,
+        D: Into<String>,
+    {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<I, O> Tool for ExternalTool<I, O>
+where
+    I: JsonSchema,
+    O: JsonSchema,
+{
+    type Input = I;
+    type Output = O;
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn external(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, _: Value) -> Result<String> {
+        bail!(
+            "Tool `{}` is external and must be executed by the application",
+            self.name
+        )
+    }
+}
+
+/// A tool call of an assistant message.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ToolCall {
+    Completed {
+        result: ToolResult,
+    },
+    /// A call to an external tool, waiting for its result to be submitted.
+    Pending {
+        tool_use: ToolUse,
+    },
+}
+
+/// The execution of the tool calls of an assistant message, which is suspended while some
+/// calls to external tools are pending.
+///
+/// The state can be serialized, so that the execution can be resumed later on, possibly by a
+/// different process.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct ToolExecution {
+    pub calls: Vec<ToolCall>,
+}
+
+impl ToolExecution {
+    /// The tool uses waiting for their result to be submitted.
+    pub fn pending(&self) -> impl Iterator<Item = &ToolUse> + '_ {
+        self.calls.iter().filter_map(|call| match call {
+            ToolCall::Pending { tool_use } => Some(tool_use),
+            ToolCall::Completed { .. } => None,
+        })
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.pending().next().is_some()
+    }
+
+    /// Submits the result of a pending tool use, matched by id.
+    pub fn submit(&mut self, result: ToolResult) -> Result<()> {
+        let call = self
+            .calls
+            .iter_mut()
+            .find(|call| matches!(call, ToolCall::Pending { tool_use } if tool_use.id == result.id))
+            .ok_or_else(|| anyhow!("No pending tool use with id {}", result.id))?;
+        *call = ToolCall::Completed { result };
+        Ok(())
+    }
+
+    /// Turns the completed execution into the message answering the tool uses.
+    pub fn into_message(self) -> Result<Message> {
+        let content = self
+            .calls
+            .into_iter()
+            .map(|call| match call {
+                ToolCall::Completed { result } => Ok(Content::ToolResult(result)),
+                ToolCall::Pending { tool_use } => {
+                    Err(anyhow!("Tool use {} is still pending", tool_use.id))
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Message {
+            role: Role::User,
+            content,
+            ..Default::default()
+        })
+    }
+}
+
+/// The decision of a [`ToolPolicy`] about a tool use.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ToolDecision {
+    Allow,
+    Deny {
+        reason: String,
+    },
+    /// The tool use must be approved by the [`ToolApprover`] before being executed.
+    RequireApproval,
 }
 
+/// Decides whether a tool use may be executed, before it is dispatched by the
+/// [`ToolProvider`].
 #[async_trait]
-trait AnyTool: Send + Sync {
+pub trait ToolPolicy: Send + Sync {
+    async fn decide(&self, tool_use: &ToolUse) -> Result<ToolDecision>;
+}
+
+#[async_trait]
+impl<F> ToolPolicy for F
+where
+    F: Fn(&ToolUse) -> ToolDecision + Send + Sync,
+{
+    async fn decide(&self, tool_use: &ToolUse) -> Result<ToolDecision> {
+        Ok(self(tool_use))
+    }
+}
+
+/// The answer of a [`ToolApprover`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ToolApproval {
+    Approved,
+    Rejected { reason: Option<String> },
+}
+
+/// Approves the tool uses for which the [`ToolPolicy`] requires it, typically by asking a
+/// human.
+///
+/// Approvals happen before the concurrency limit is applied, so that waiting for an answer
+/// doesn't hold back other tool calls.
+#[async_trait]
+pub trait ToolApprover: Send + Sync {
+    async fn approve(&self, tool_use: &ToolUse) -> Result<ToolApproval>;
+}
+
+/// A tool whose schema is only known at runtime, such as a tool discovered from a remote server.
+///
+/// Every [`Tool`] is also a [`DynamicTool`].
+#[async_trait]
+pub trait DynamicTool: Send + Sync {
     fn schema(&self) -> ToolDescriptor;
-    async fn execute(&self, input: Value) -> Result<String>;
+
+    async fn execute(&self, input: Value) -> Result<Vec<Content>>;
 }
 
 #[async_trait]
-impl<T: Tool + Send + Sync + 'static> AnyTool for T {
+impl<T: Tool + Send + Sync + 'static> DynamicTool for T {
     fn schema(&self) -> ToolDescriptor {
         Tool::schema(self)
     }
 
-    async fn execute(&self, input: Value) -> Result<String> {
-        Tool::execute(self, input).await
+    async fn execute(&self, input: Value) -> Result<Vec<Content>> {
+        Tool::execute_content(self, input).await
+    }
+}
+
+#[async_trait]
+impl DynamicTool for Arc<dyn DynamicTool> {
+    fn schema(&self) -> ToolDescriptor {
+        self.as_ref().schema()
+    }
+
+    async fn execute(&self, input: Value) -> Result<Vec<Content>> {
+        self.as_ref().execute(input).await
     }
 }
 
@@ -66,42 +350,354 @@ where
 }
 
 #[derive(Clone)]
-pub struct ToolProvider(HashMap<String, Arc<dyn AnyTool>>);
+struct RegisteredTool {
+    tool: Arc<dyn DynamicTool>,
+    external: bool,
+    input: Result<Option<Arc<Validator>>, String>,
+    output: Result<Option<Arc<Validator>>, String>,
+}
+
+/// Compiles `schema`, returning why it isn't a valid JSON schema otherwise. The schema of `()`,
+/// used by the tools which don't describe their input or output, isn't checked.
+fn validator(schema: &RootSchema) -> Result<Option<Arc<Validator>>, String> {
+    if *schema == schema_for!(()) {
+        return Ok(None);
+    }
+
+    let schema = serde_json::to_value(schema).map_err(|err| err.to_string())?;
+    jsonschema::validator_for(&schema)
+        .map(|validator| Some(Arc::new(validator)))
+        .map_err(|err| err.to_string())
+}
+
+fn validation_errors(validator: &Validator, instance: &Value) -> Vec<String> {
+    validator
+        .iter_errors(instance)
+        .map(|error| {
+            let path = error.instance_path.to_string();
+            format!("{}: {}", if path.is_empty() { "/" } else { &path }, error)
+        })
+        .collect()
+}
+
+/// Checks that the input of a tool is an object, or `()`, whose schema is `null`. Schemas
+/// without a type, such as the one of any JSON value, are accepted.
+fn check_input(descriptor: &ToolDescriptor) -> Result<()> {
+    match &descriptor.input.schema.instance_type {
+        None => Ok(()),
+        Some(SingleOrVec::Single(kind))
+            if matches!(**kind, InstanceType::Object | InstanceType::Null) =>
+        {
+            Ok(())
+        }
+        Some(SingleOrVec::Vec(kinds)) if kinds.contains(&InstanceType::Object) => Ok(()),
+        Some(kind) => bail!(
+            "the input of tool `{}` must be an object, not {}",
+            descriptor.name,
+            serde_json::to_string(kind)?
+        ),
+    }
+}
+
+/// A collection of tools which can be advertised to and called by a model.
+///
+/// Before being dispatched, the input of each [`ToolUse`] is validated against the tool's
+/// input schema. Invalid inputs, calls to unknown tools or to tools whose schema doesn't
+/// compile, and tool failures are answered with an error [`ToolResult`] describing the
+/// problem, so that the model can react to it instead of the whole conversation being
+/// aborted.
+#[derive(Clone, Default)]
+pub struct ToolProvider {
+    tools: HashMap<String, RegisteredTool>,
+    validate_output: bool,
+    timeout: Option<Duration>,
+    timeouts: HashMap<String, Duration>,
+    concurrency: Option<Arc<Semaphore>>,
+    policy: Option<Arc<dyn ToolPolicy>>,
+    approver: Option<Arc<dyn ToolApprover>>,
+}
 
 impl ToolProvider {
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn register<T>(&mut self, tool: T)
+    /// Validates tool outputs against their output schema, turning mismatching outputs into
+    /// error results.
+    pub fn with_output_validation(mut self, validate_output: bool) -> Self {
+        self.validate_output = validate_output;
+        self
+    }
+
+    /// Sets the time after which the execution of a tool is aborted, unless the tool has its
+    /// own timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the time after which the execution of the tool named `name` is aborted.
+    pub fn with_tool_timeout<S>(mut self, name: S, timeout: Duration) -> Self
+    where
+        S: Into<String>,
+    {
+        self.timeouts.insert(name.into(), timeout);
+        self
+    }
+
+    /// Limits the number of tools executed at the same time, across every call to the
+    /// provider and its clones. A limit of 0 is treated as 1.
+    pub fn with_concurrency_limit(mut self, limit: usize) -> Self {
+        self.concurrency = Some(Arc::new(Semaphore::new(limit.max(1))));
+        self
+    }
+
+    /// Sets the policy deciding whether each tool use may be executed. Denied tool uses are
+    /// answered with an error result.
+    pub fn with_policy<P>(mut self, policy: P) -> Self
+    where
+        P: ToolPolicy + 'static,
+    {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Sets the approver of the tool uses for which the policy requires an approval. Without
+    /// an approver, such tool uses are denied.
+    pub fn with_approver<A>(mut self, approver: A) -> Self
+    where
+        A: ToolApprover + 'static,
+    {
+        self.approver = Some(Arc::new(approver));
+        self
+    }
+
+    /// Registers a tool, replacing any tool of the same name.
+    ///
+    /// Fails if the input of the tool isn't an object, which is what providers expect, unless
+    /// it is `()` for tools without input.
+    pub fn register<T>(&mut self, tool: T) -> Result<()>
     where
         T: Tool + Send + Sync + 'static,
     {
-        self.0
-            .insert(tool.name().to_string(), Arc::new(tool) as Arc<dyn AnyTool>);
+        self.register_dynamic(tool)
+    }
+
+    /// Registers a dynamic tool, failing like [`register`](Self::register) does.
+    pub fn register_dynamic<T>(&mut self, tool: T) -> Result<()>
+    where
+        T: DynamicTool + 'static,
+    {
+        let descriptor = DynamicTool::schema(&tool);
+        check_input(&descriptor)?;
+        self.tools.insert(
+            descriptor.name,
+            RegisteredTool {
+                tool: Arc::new(tool) as Arc<dyn DynamicTool>,
+                external: descriptor.external,
+                input: validator(&descriptor.input),
+                output: validator(&descriptor.output),
+            },
+        );
+        Ok(())
     }
 
     pub async fn execute(&self, tool_use: &ToolUse) -> Result<ToolResult> {
-        let Some(tool) = self.0.get(&tool_use.tool) else {
-            bail!("Tool not found: {}", tool_use.tool)
+        let Some(registered) = self.tools.get(&tool_use.tool) else {
+            let mut tools = self.tools.keys().map(String::as_str).collect::<Vec<_>>();
+            tools.sort();
+            return Ok(ToolResult::error(
+                &tool_use.id,
+                format!(
+                    "Tool `{}` does not exist. Available tools are: {}.",
+                    tool_use.tool,
+                    tools.join(", ")
+                ),
+            ));
         };
 
-        let result = tool.execute(tool_use.input.clone()).await?;
+        let input = match &registered.input {
+            Ok(input) => input,
+            Err(err) => {
+                return Ok(ToolResult::error(
+                    &tool_use.id,
+                    format!(
+                        "Tool `{}` has an invalid input schema: {}",
+                        tool_use.tool, err
+                    ),
+                ))
+            }
+        };
+        if let Some(validator) = input {
+            let errors = validation_errors(validator, &tool_use.input);
+            if !errors.is_empty() {
+                return Ok(ToolResult::error(
+                    &tool_use.id,
+                    format!(
+                        "Invalid input for tool `{}`:\n- {}\nFix the input and call the tool again.",
+                        tool_use.tool,
+                        errors.join("\n- ")
+                    ),
+                ));
+            }
+        }
 
-        Ok(ToolResult {
-            id: tool_use.id.clone(),
-            content: result,
-        })
+        if let Some(reason) = self.denial(tool_use).await? {
+            return Ok(ToolResult::error(
+                &tool_use.id,
+                format!("Tool `{}` was denied: {}", tool_use.tool, reason),
+            ));
+        }
+
+        let _permit = match &self.concurrency {
+            Some(semaphore) => Some(semaphore.acquire().await?),
+            None => None,
+        };
+
+        let execution = registered.tool.execute(tool_use.input.clone());
+        let result = match self.timeouts.get(&tool_use.tool).or(self.timeout.as_ref()) {
+            Some(timeout) => match tokio::time::timeout(*timeout, execution).await {
+                Ok(result) => result,
+                Err(_) => {
+                    return Ok(ToolResult::error(
+                        &tool_use.id,
+                        format!("Tool `{}` timed out after {:?}", tool_use.tool, timeout),
+                    ))
+                }
+            },
+            None => execution.await,
+        };
+
+        let content = match result {
+            Ok(content) => content,
+            Err(err) => {
+                return Ok(ToolResult::error(
+                    &tool_use.id,
+                    format!("Tool `{}` failed: {:#}", tool_use.tool, err),
+                ))
+            }
+        };
+
+        let output = match (self.validate_output, &registered.output) {
+            (false, _) => &None,
+            (true, Ok(output)) => output,
+            (true, Err(err)) => {
+                return Ok(ToolResult::error(
+                    &tool_use.id,
+                    format!(
+                        "Tool `{}` has an invalid output schema: {}",
+                        tool_use.tool, err
+                    ),
+                ))
+            }
+        };
+        if let (Some(validator), [Content::Text { text }]) = (output, content.as_slice()) {
+            // Plain string outputs are returned as they are, so they are checked both as JSON
+            // and as a JSON string.
+            let errors = match serde_json::from_str::<Value>(text) {
+                Ok(output) if validator.is_valid(&output) => vec![],
+                _ => validation_errors(validator, &Value::String(text.clone())),
+            };
+            if !errors.is_empty() {
+                return Ok(ToolResult::error(
+                    &tool_use.id,
+                    format!(
+                        "Tool `{}` returned an invalid output: {}",
+                        tool_use.tool,
+                        errors.join(", ")
+                    ),
+                ));
+            }
+        }
+
+        Ok(ToolResult::new(&tool_use.id, content))
+    }
+
+    /// Returns the reason why `tool_use` may not be executed, if any.
+    async fn denial(&self, tool_use: &ToolUse) -> Result<Option<String>> {
+        let Some(policy) = &self.policy else {
+            return Ok(None);
+        };
+
+        match policy.decide(tool_use).await? {
+            ToolDecision::Allow => Ok(None),
+            ToolDecision::Deny { reason } => Ok(Some(reason)),
+            ToolDecision::RequireApproval => match &self.approver {
+                Some(approver) => match approver.approve(tool_use).await? {
+                    ToolApproval::Approved => Ok(None),
+                    ToolApproval::Rejected { reason } => Ok(Some(
+                        reason.unwrap_or_else(|| "the call was not approved".into()),
+                    )),
+                },
+                None => Ok(Some(
+                    "the call requires an approval, but no approver is configured".into(),
+                )),
+            },
+        }
     }
 
+    /// Executes every tool use of `message` concurrently, within the concurrency limit.
+    ///
+    /// Calls to external tools are not executed: they are left pending in the returned
+    /// [`ToolExecution`], for the application to submit their results.
+    pub async fn execute_all(&self, message: &Message) -> Result<ToolExecution> {
+        let calls = join_all(message.tool_use().map(|tool_use| async move {
+            match self.tools.get(&tool_use.tool) {
+                Some(registered) if registered.external => Ok(ToolCall::Pending {
+                    tool_use: tool_use.clone(),
+                }),
+                _ => Ok(ToolCall::Completed {
+                    result: self.execute(tool_use).await?,
+                }),
+            }
+        }))
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+
+        Ok(ToolExecution { calls })
+    }
+
+    /// Returns the descriptors of every tool, sorted by name so that requests built from them
+    /// are identical from one process to the next, as prompt caching requires.
     pub fn list(&self) -> impl Iterator<Item = ToolDescriptor> + '_ {
-        self.0.values().map(|tool| tool.schema())
+        let mut descriptors = self
+            .tools
+            .values()
+            .map(|registered| registered.tool.schema())
+            .collect::<Vec<_>>();
+        descriptors.sort_by(|a, b| a.name.cmp(&b.name));
+        descriptors.into_iter()
     }
-}
 
-impl Default for ToolProvider {
-    fn default() -> Self {
-        Self(HashMap::new())
+    /// Returns the descriptors of the tools described by `selection`.
+    ///
+    /// Fails if the selection refers to a tool which isn't registered, or forces the use of a
+    /// tool which isn't part of the selection.
+    pub fn select(&self, selection: &ToolSelection) -> Result<Vec<ToolDescriptor>> {
+        let descriptors = match &selection.tools {
+            Some(names) => names
+                .iter()
+                .map(|name| {
+                    self.tools
+                        .get(name)
+                        .map(|registered| registered.tool.schema())
+                        .ok_or_else(|| anyhow!("Tool not found: {}", name))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            None => self.list().collect(),
+        };
+
+        if let ToolChoice::Tool { name } = &selection.choice {
+            if !descriptors
+                .iter()
+                .any(|descriptor| &descriptor.name == name)
+            {
+                bail!("Tool `{}` is forced but not selected", name);
+            }
+        }
+
+        Ok(descriptors)
     }
 }
 
@@ -135,7 +731,7 @@ mod tests {
     async fn test_echo_tool() {
         let mut provider = ToolProvider::new();
 
-        provider.register(EchoTool);
+        provider.register(EchoTool).unwrap();
 
         let input = json!({"message": "Hello, world!"});
         let output = provider
@@ -147,6 +743,423 @@ mod tests {
             .await
             .unwrap();
 
-        assert_eq!(input, output.content);
+        assert_eq!(input.to_string(), output.text());
+    }
+
+    struct PatternTool(&'static str);
+
+    #[async_trait]
+    impl Tool for PatternTool {
+        type Input = Value;
+        type Output = ();
+
+        fn name(&self) -> String {
+            "pattern".into()
+        }
+
+        fn description(&self) -> String {
+            "Accepts the strings matching a pattern".into()
+        }
+
+        fn schema(&self) -> ToolDescriptor {
+            ToolDescriptor {
+                name: self.name(),
+                description: self.description(),
+                input: serde_json::from_value(json!({
+                    "type": "object",
+                    "properties": { "text": { "type": "string", "pattern": self.0 } },
+                }))
+                .unwrap(),
+                output: schema_for!(()),
+                external: false,
+            }
+        }
+
+        async fn execute(&self, input: Value) -> Result<String> {
+            Ok(input.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_input_schemas() {
+        let execute = |tool: PatternTool, input: Value| async move {
+            let mut provider = ToolProvider::new();
+            provider.register(tool).unwrap();
+            provider
+                .execute(&ToolUse {
+                    id: "1".into(),
+                    tool: "pattern".into(),
+                    input,
+                })
+                .await
+                .unwrap()
+        };
+
+        let result = execute(PatternTool("^a+$"), json!({ "text": "aaa" })).await;
+        assert!(!result.is_error);
+
+        let result = execute(PatternTool("^a+$"), json!({ "text": "abc" })).await;
+        assert!(result.is_error);
+        assert!(result
+            .text()
+            .starts_with("Invalid input for tool `pattern`:"));
+
+        let result = execute(PatternTool("("), json!({ "text": "abc" })).await;
+        assert!(result.is_error);
+        assert!(result
+            .text()
+            .starts_with("Tool `pattern` has an invalid input schema:"));
+    }
+
+    #[derive(JsonSchema, serde::Deserialize)]
+    struct AddInput {
+        a: i64,
+        b: i64,
+    }
+
+    /// Adds two numbers.
+    ///
+    /// Both numbers must be integers.
+    #[crate::tool]
+    async fn add(input: AddInput) -> Result<i64> {
+        Ok(input.a + input.b)
+    }
+
+    #[derive(JsonSchema, serde::Deserialize)]
+    struct GreetInput {
+        name: String,
+    }
+
+    #[tokio::test]
+    async fn test_fn_and_attribute_tools() {
+        let mut provider = ToolProvider::new();
+
+        provider
+            .register(FnTool::new(
+                "greet",
+                "Greets someone",
+                |input: GreetInput| async move { Ok(format!("Hello, {}!", input.name)) },
+            ))
+            .unwrap();
+        provider.register(AddTool).unwrap();
+
+        let greeting = provider
+            .execute(&ToolUse {
+                id: "1".into(),
+                tool: "greet".into(),
+                input: json!({ "name": "Ferris" }),
+            })
+            .await
+            .unwrap();
+        assert_eq!(greeting.text(), "Hello, Ferris!");
+
+        let sum = provider
+            .execute(&ToolUse {
+                id: "2".into(),
+                tool: "add".into(),
+                input: json!({ "a": 1, "b": 2 }),
+            })
+            .await
+            .unwrap();
+        assert_eq!(sum.text(), "3");
+
+        let descriptor = provider.list().find(|tool| tool.name == "add").unwrap();
+        assert_eq!(
+            descriptor.description,
+            "Adds two numbers.\n\nBoth numbers must be integers."
+        );
+    }
+
+    #[test]
+    fn test_non_object_inputs_are_rejected() {
+        let mut provider = ToolProvider::new();
+        let error = provider
+            .register(FnTool::new("shout", "Shouts", |text: String| async move {
+                Ok(text.to_uppercase())
+            }))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the input of tool `shout` must be an object, not \"string\""
+        );
+        assert_eq!(provider.list().count(), 0);
+
+        provider
+            .register(FnTool::new("now", "Tells the time", |_: ()| async {
+                Ok(0)
+            }))
+            .unwrap();
+        provider
+            .register(ExternalTool::<Value, String>::new("ask", "Asks"))
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_input_is_reported_to_the_model() {
+        let mut provider = ToolProvider::new().with_output_validation(true);
+        provider.register(AddTool).unwrap();
+
+        let result = provider
+            .execute(&ToolUse {
+                id: "1".into(),
+                tool: "add".into(),
+                input: json!({ "a": "one" }),
+            })
+            .await
+            .unwrap();
+        assert!(result.is_error);
+        assert!(result.text().starts_with("Invalid input for tool `add`:"));
+        assert!(result
+            .text()
+            .contains("/a: \"one\" is not of type \"integer\""));
+        assert!(result.text().contains("\"b\" is a required property"));
+
+        let result = provider
+            .execute(&ToolUse {
+                id: "2".into(),
+                tool: "subtract".into(),
+                input: json!({}),
+            })
+            .await
+            .unwrap();
+        assert!(result.is_error);
+        assert_eq!(
+            result.text(),
+            "Tool `subtract` does not exist. Available tools are: add."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tool_failures_become_error_results() {
+        let mut provider = ToolProvider::new();
+        provider
+            .register(FnTool::new("fail", "Always fails", |_: Value| async move {
+                anyhow::Result::<()>::Err(anyhow::anyhow!("boom"))
+            }))
+            .unwrap();
+
+        let result = provider
+            .execute(&ToolUse {
+                id: "1".into(),
+                tool: "fail".into(),
+                input: json!(null),
+            })
+            .await
+            .unwrap();
+
+        assert!(result.is_error);
+        assert_eq!(result.text(), "Tool `fail` failed: boom");
+    }
+
+    #[test]
+    fn test_select_tools() {
+        let mut provider = ToolProvider::new();
+        provider.register(EchoTool).unwrap();
+        provider.register(AddTool).unwrap();
+
+        let selected = provider.select(&ToolSelection::tool("add")).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].name, "add");
+
+        let names = provider
+            .select(&ToolSelection::default())
+            .unwrap()
+            .into_iter()
+            .map(|descriptor| descriptor.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["add", "echo"]);
+        assert!(provider.select(&ToolSelection::only(["delete"])).is_err());
+        assert!(provider
+            .select(
+                &ToolSelection::only(["echo"]).with_choice(ToolChoice::Tool { name: "add".into() })
+            )
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_external_tools_suspend_execution() {
+        let mut provider = ToolProvider::new();
+        provider.register(EchoTool).unwrap();
+        provider
+            .register(ExternalTool::<Value, String>::new(
+                "ask_user",
+                "Asks the user a question",
+            ))
+            .unwrap();
+
+        assert!(provider
+            .list()
+            .any(|tool| tool.name == "ask_user" && tool.external));
+
+        let message = Message {
+            role: Role::Assistant,
+            content: vec![
+                Content::ToolUse(ToolUse {
+                    id: "1".into(),
+                    tool: "echo".into(),
+                    input: json!("hi"),
+                }),
+                Content::ToolUse(ToolUse {
+                    id: "2".into(),
+                    tool: "ask_user".into(),
+                    input: json!("What's your name?"),
+                }),
+            ],
+            ..Default::default()
+        };
+
+        let execution = provider.execute_all(&message).await.unwrap();
+        assert!(execution.is_suspended());
+        assert_eq!(execution.pending().next().unwrap().id, "2");
+
+        let state = serde_json::to_string(&execution).unwrap();
+        let mut execution: ToolExecution = serde_json::from_str(&state).unwrap();
+        assert!(execution.clone().into_message().is_err());
+
+        execution
+            .submit(ToolResult::new("2", vec!["Ferris".into()]))
+            .unwrap();
+        assert!(!execution.is_suspended());
+
+        let message = execution.into_message().unwrap();
+        let results = message
+            .content
+            .iter()
+            .map(|content| match content {
+                Content::ToolResult(result) => (result.id.as_str(), result.text()),
+                _ => panic!("expected a tool result"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            vec![("1", "\"hi\"".to_string()), ("2", "Ferris".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_policies_and_timeouts() {
+        struct Approver;
+
+        #[async_trait]
+        impl ToolApprover for Approver {
+            async fn approve(&self, tool_use: &ToolUse) -> Result<ToolApproval> {
+                Ok(match tool_use.input.as_str() {
+                    Some("please") => ToolApproval::Approved,
+                    _ => ToolApproval::Rejected { reason: None },
+                })
+            }
+        }
+
+        let mut provider = ToolProvider::new()
+            .with_tool_timeout("slow", Duration::from_millis(10))
+            .with_policy(|tool_use: &ToolUse| match tool_use.tool.as_str() {
+                "slow" => ToolDecision::Allow,
+                "echo" => ToolDecision::RequireApproval,
+                _ => ToolDecision::Deny {
+                    reason: "not allowed".into(),
+                },
+            })
+            .with_approver(Approver);
+        provider.register(EchoTool).unwrap();
+        provider
+            .register(FnTool::new("slow", "Never ends", |_: Value| async {
+                std::future::pending::<Result<()>>().await
+            }))
+            .unwrap();
+        provider
+            .register(FnTool::new("rm", "Removes files", |_: Value| async {
+                Ok(())
+            }))
+            .unwrap();
+
+        let execute = |tool: &str, input: Value| {
+            let tool_use = ToolUse {
+                id: "1".into(),
+                tool: tool.into(),
+                input,
+            };
+            let provider = provider.clone();
+            async move { provider.execute(&tool_use).await.unwrap() }
+        };
+
+        let result = execute("rm", json!(null)).await;
+        assert!(result.is_error);
+        assert_eq!(result.text(), "Tool `rm` was denied: not allowed");
+
+        let result = execute("echo", json!("please")).await;
+        assert!(!result.is_error);
+
+        let result = execute("echo", json!("now")).await;
+        assert!(result.is_error);
+        assert_eq!(
+            result.text(),
+            "Tool `echo` was denied: the call was not approved"
+        );
+
+        let result = execute("slow", json!(null)).await;
+        assert!(result.is_error);
+        assert_eq!(result.text(), "Tool `slow` timed out after 10ms");
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let mut provider = ToolProvider::new().with_concurrency_limit(2);
+        provider
+            .register(FnTool::new("work", "Works for a while", {
+                let running = running.clone();
+                let peak = peak.clone();
+                move |_: Value| {
+                    let running = running.clone();
+                    let peak = peak.clone();
+                    async move {
+                        let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(current, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        Ok(())
+                    }
+                }
+            }))
+            .unwrap();
+
+        let message = Message {
+            role: Role::Assistant,
+            content: (0..5)
+                .map(|id| {
+                    Content::ToolUse(ToolUse {
+                        id: id.to_string(),
+                        tool: "work".into(),
+                        input: json!(null),
+                    })
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        let execution = provider.execute_all(&message).await.unwrap();
+        assert_eq!(execution.calls.len(), 5);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_zero_concurrency_limit() {
+        let mut provider = ToolProvider::new().with_concurrency_limit(0);
+        provider.register(EchoTool).unwrap();
+
+        let tool_use = ToolUse {
+            id: "1".into(),
+            tool: "echo".into(),
+            input: json!("hi"),
+        };
+        let result = tokio::time::timeout(Duration::from_secs(1), provider.execute(&tool_use))
+            .await
+            .expect("a limit of 0 lets one tool run at a time")
+            .unwrap();
+        assert_eq!(result.text(), "\"hi\"");
     }
 }
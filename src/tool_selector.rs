@@ -0,0 +1,417 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+use crate::{
+    completion::{Completion, CompletionOptions, CompletionResponse, ToolChoice, ToolSelection},
+    embedding::Embedder,
+    message::{Content, Message, Role},
+    tool::ToolProvider,
+};
+
+/// Selects the tools relevant to a conversation, out of the tools of a [`ToolProvider`].
+///
+/// The name and description of each tool are embedded once, when the selector is built. For
+/// every request, the text of the latest user messages, along with the text of the tool results
+/// which followed them, is embedded and the `top_k` most similar tools are selected, along with
+/// the tools which must always be included.
+pub struct ToolSelector {
+    embedder: Arc<dyn Embedder>,
+    tools: Vec<(String, Vec<f32>)>,
+    top_k: usize,
+    always_include: Vec<String>,
+    context_messages: usize,
+}
+
+pub struct ToolSelectorBuilder {
+    embedder: Option<Arc<dyn Embedder>>,
+    tool_provider: Option<ToolProvider>,
+    top_k: usize,
+    always_include: Vec<String>,
+    context_messages: usize,
+}
+
+impl ToolSelector {
+    pub fn builder() -> ToolSelectorBuilder {
+        ToolSelectorBuilder {
+            embedder: None,
+            tool_provider: None,
+            top_k: 5,
+            always_include: vec![],
+            context_messages: 1,
+        }
+    }
+
+    /// Selects the tools relevant to `messages`.
+    ///
+    /// Every tool is selected when the conversation contains no text to compare the tools with.
+    pub async fn select(&self, messages: &[Message]) -> Result<ToolSelection> {
+        let query = self.query(messages);
+        if query.trim().is_empty() {
+            return Ok(ToolSelection::default());
+        }
+
+        let query = self
+            .embedder
+            .embed(vec![query])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("the embedder returned no embedding"))?
+            .to_vec();
+
+        let mut scores = self
+            .tools
+            .iter()
+            .filter(|(name, _)| !self.always_include.contains(name))
+            .map(|(name, embedding)| (name, cosine_similarity(&query, embedding)))
+            .collect::<Vec<_>>();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        Ok(ToolSelection::only(
+            self.always_include.iter().cloned().chain(
+                scores
+                    .into_iter()
+                    .take(self.top_k)
+                    .map(|(name, _)| name.clone()),
+            ),
+        ))
+    }
+
+    /// The text compared with the tools: the text of the latest `context_messages` user
+    /// messages with text, followed by the text of the tool results sent since the latest one.
+    ///
+    /// In an agent loop the latest message is usually a tool result, so the user messages are
+    /// searched for further back.
+    fn query(&self, messages: &[Message]) -> String {
+        let mut texts = vec![];
+        let mut tool_results = vec![];
+        let mut user_messages = 0;
+        for message in messages.iter().rev() {
+            if user_messages == self.context_messages {
+                break;
+            }
+            if message.role == Role::User {
+                let text = text(&message.content);
+                if !text.trim().is_empty() {
+                    texts.push(text);
+                    user_messages += 1;
+                }
+            }
+            if user_messages == 0 {
+                tool_results.extend(message.content.iter().rev().filter_map(
+                    |content| match content {
+                        Content::ToolResult(result) => Some(text(&result.content)),
+                        _ => None,
+                    },
+                ));
+            }
+        }
+
+        texts
+            .into_iter()
+            .rev()
+            .chain(tool_results.into_iter().rev())
+            .filter(|text| !text.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl ToolSelectorBuilder {
+    pub fn with_embedder(mut self, embedder: Arc<dyn Embedder>) -> Self {
+        self.embedder = Some(embedder);
+        self
+    }
+
+    pub fn with_tool_provider(mut self, tool_provider: ToolProvider) -> Self {
+        self.tool_provider = Some(tool_provider);
+        self
+    }
+
+    /// Sets the number of tools selected by similarity, 5 by default.
+    pub fn with_top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Sets the tools which are always selected, in addition to the `top_k` most similar ones.
+    pub fn with_always_include<I, S>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.always_include = tools.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the number of latest user messages compared with the tools, 1 by default.
+    pub fn with_context_messages(mut self, context_messages: usize) -> Self {
+        self.context_messages = context_messages;
+        self
+    }
+
+    /// Embeds the tools of the tool provider.
+    pub async fn build(self) -> Result<ToolSelector> {
+        let embedder = self
+            .embedder
+            .ok_or_else(|| anyhow!("embedder is required"))?;
+        let tool_provider = self
+            .tool_provider
+            .ok_or_else(|| anyhow!("tool_provider is required"))?;
+
+        let descriptors = tool_provider.list().collect::<Vec<_>>();
+        for name in &self.always_include {
+            if !descriptors
+                .iter()
+                .any(|descriptor| &descriptor.name == name)
+            {
+                return Err(anyhow!("Tool not found: {}", name));
+            }
+        }
+
+        let embeddings = if descriptors.is_empty() {
+            vec![]
+        } else {
+            embedder
+                .embed(
+                    descriptors
+                        .iter()
+                        .map(|descriptor| {
+                            format!("{}: {}", descriptor.name, descriptor.description)
+                        })
+                        .collect(),
+                )
+                .await?
+        };
+        if embeddings.len() != descriptors.len() {
+            return Err(anyhow!(
+                "expected {} embeddings, the embedder returned {}",
+                descriptors.len(),
+                embeddings.len()
+            ));
+        }
+
+        Ok(ToolSelector {
+            embedder,
+            tools: descriptors
+                .into_iter()
+                .zip(embeddings)
+                .map(|(descriptor, embedding)| (descriptor.name, embedding.to_vec()))
+                .collect(),
+            top_k: self.top_k,
+            always_include: self.always_include,
+            context_messages: self.context_messages,
+        })
+    }
+}
+
+fn text(content: &[Content]) -> String {
+    content
+        .iter()
+        .filter_map(|content| match content {
+            Content::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot = a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+    let norm = |vector: &[f32]| vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+    match norm(a) * norm(b) {
+        0.0 => 0.0,
+        norms => dot / norms,
+    }
+}
+
+/// A [`Completion`] advertising only the tools selected by a [`ToolSelector`] for each
+/// request.
+///
+/// Requests which already restrict their tools through [`Completion::complete_with_tools`]
+/// are left untouched.
+pub struct ToolSelectingCompletion {
+    completion: Arc<dyn Completion>,
+    selector: Arc<ToolSelector>,
+}
+
+impl ToolSelectingCompletion {
+    pub fn new(completion: Arc<dyn Completion>, selector: Arc<ToolSelector>) -> Self {
+        Self {
+            completion,
+            selector,
+        }
+    }
+
+    /// Selects the tools of the request, unless it already restricts them.
+    async fn select(&self, messages: &[Message], tools: ToolSelection) -> Result<ToolSelection> {
+        if tools.tools.is_some() {
+            return Ok(tools);
+        }
+
+        let mut selection = self.selector.select(messages).await?;
+        // A tool the model is forced to use must be advertised, however relevant.
+        if let (ToolChoice::Tool { name }, Some(selected)) = (&tools.choice, &mut selection.tools) {
+            if !selected.contains(name) {
+                selected.push(name.clone());
+            }
+        }
+        Ok(selection
+            .with_choice(tools.choice)
+            .with_cache_breakpoint(tools.cache_breakpoint))
+    }
+}
+
+#[async_trait]
+impl Completion for ToolSelectingCompletion {
+    async fn complete(&self, messages: Vec<Message>) -> Result<CompletionResponse> {
+        self.complete_with_tools(messages, ToolSelection::default())
+            .await
+    }
+
+    async fn complete_with_tools(
+        &self,
+        messages: Vec<Message>,
+        tools: ToolSelection,
+    ) -> Result<CompletionResponse> {
+        let tools = self.select(&messages, tools).await?;
+        self.completion.complete_with_tools(messages, tools).await
+    }
+
+    async fn complete_with_options(
+        &self,
+        messages: Vec<Message>,
+        mut options: CompletionOptions,
+    ) -> Result<CompletionResponse> {
+        let tools = options.tools.take().unwrap_or_default();
+        options.tools = Some(self.select(&messages, tools).await?);
+        self.completion
+            .complete_with_options(messages, options)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        embedding::Embedding,
+        message::{ToolResult, ToolUse},
+        tool::FnTool,
+    };
+
+    /// Embeds texts as the number of occurrences of a few keywords.
+    struct KeywordEmbedder;
+
+    #[async_trait]
+    impl Embedder for KeywordEmbedder {
+        async fn embed(&self, chunks: Vec<String>) -> Result<Vec<Embedding>> {
+            Ok(chunks
+                .iter()
+                .map(|chunk| {
+                    let chunk = chunk.to_lowercase();
+                    ["weather", "email", "calendar", "file"]
+                        .iter()
+                        .map(|keyword| chunk.matches(keyword).count() as f32)
+                        .collect::<Vec<_>>()
+                        .into()
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_select_top_k_tools() {
+        let mut provider = ToolProvider::new();
+        for (name, description) in [
+            ("forecast", "Tells the weather forecast"),
+            ("send_email", "Sends an email"),
+            ("schedule", "Adds an event to the calendar"),
+            ("read_file", "Reads a file"),
+        ] {
+            provider
+                .register(FnTool::new(name, description, |_: Value| async { Ok(()) }))
+                .unwrap();
+        }
+
+        let selector = ToolSelector::builder()
+            .with_embedder(Arc::new(KeywordEmbedder))
+            .with_tool_provider(provider)
+            .with_top_k(1)
+            .with_always_include(["read_file"])
+            .build()
+            .await
+            .unwrap();
+
+        let selection = selector
+            .select(&[Message {
+                role: Role::User,
+                content: vec!["Will the weather be nice tomorrow?".into()],
+                ..Default::default()
+            }])
+            .await
+            .unwrap();
+        assert_eq!(
+            selection.tools.unwrap(),
+            vec!["read_file".to_string(), "forecast".to_string()]
+        );
+
+        let selection = selector.select(&[]).await.unwrap();
+        assert!(selection.tools.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_select_after_tool_result() {
+        let mut provider = ToolProvider::new();
+        for (name, description) in [
+            ("forecast", "Tells the weather forecast"),
+            ("send_email", "Sends an email"),
+            ("schedule", "Adds an event to the calendar"),
+        ] {
+            provider
+                .register(FnTool::new(name, description, |_: Value| async { Ok(()) }))
+                .unwrap();
+        }
+
+        let selector = ToolSelector::builder()
+            .with_embedder(Arc::new(KeywordEmbedder))
+            .with_tool_provider(provider)
+            .with_top_k(1)
+            .build()
+            .await
+            .unwrap();
+
+        let messages = [
+            Message {
+                role: Role::User,
+                content: vec!["Send an email about tomorrow's weather.".into()],
+                ..Default::default()
+            },
+            Message {
+                role: Role::Assistant,
+                content: vec![Content::ToolUse(ToolUse {
+                    id: "1".to_string(),
+                    tool: "forecast".to_string(),
+                    input: Value::Null,
+                })],
+                ..Default::default()
+            },
+            Message {
+                role: Role::Tool,
+                content: vec![Content::ToolResult(ToolResult::new(
+                    "1",
+                    vec!["Sunny, as the email asked.".into()],
+                ))],
+                ..Default::default()
+            },
+        ];
+        let selection = selector.select(&messages).await.unwrap();
+        assert_eq!(selection.tools.unwrap(), vec!["send_email".to_string()]);
+
+        let selection = selector.select(&messages[1..]).await.unwrap();
+        assert_eq!(selection.tools.unwrap(), vec!["send_email".to_string()]);
+    }
+}
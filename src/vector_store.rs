@@ -21,7 +21,8 @@ pub struct Similarity {
 #[async_trait]
 pub trait VectorStore: Send + Sync {
     async fn ensure_index(&self) -> Result<()>;
-    async fn add_documents(&self, documents: &[Document]) -> Result<()>;
+    /// Stores `documents`, returning their ids in the same order.
+    async fn add_documents(&self, documents: &[Document]) -> Result<Vec<String>>;
     async fn delete_documents(&self, ids: &[String]) -> Result<()>;
     async fn get_documents(&self, ids: &[String]) -> Result<Vec<StoredDocument>>;
     async fn search(&self, query: &str, limit: u64) -> Result<Vec<Similarity>>;
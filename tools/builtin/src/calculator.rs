@@ -0,0 +1,336 @@
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use ferrochain::tool::{execute_typed, Tool};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Evaluates arithmetic expressions.
+#[derive(Clone, Debug, Default)]
+pub struct CalculatorTool;
+
+impl CalculatorTool {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CalculatorInput {
+    /// The expression to evaluate, such as `2 * (3 + 4) ^ 2`.
+    ///
+    /// Supports the `+`, `-`, `*`, `/`, `%` and `^` operators, parentheses, the `pi` and `e`
+    /// constants and the `abs`, `sqrt`, `exp`, `ln`, `log10`, `sin`, `cos`, `tan`, `floor`,
+    /// `ceil`, `round`, `min` and `max` functions.
+    #[schemars(length(min = 1))]
+    expression: String,
+}
+
+#[async_trait]
+impl Tool for CalculatorTool {
+    type Input = CalculatorInput;
+    type Output = f64;
+
+    fn name(&self) -> String {
+        "calculator".into()
+    }
+
+    fn description(&self) -> String {
+        "Evaluates an arithmetic expression and returns its numeric result.".into()
+    }
+
+    async fn execute(&self, input: Value) -> Result<String> {
+        execute_typed(input, |input: CalculatorInput| async move {
+            evaluate(&input.expression)
+        })
+        .await
+    }
+}
+
+/// Evaluates an arithmetic expression.
+pub fn evaluate(expression: &str) -> Result<f64> {
+    let mut parser = Parser {
+        tokens: tokenize(expression)?,
+        position: 0,
+        depth: 0,
+    };
+
+    let value = parser.expression()?;
+    if let Some(token) = parser.tokens.get(parser.position) {
+        bail!("unexpected {}", token);
+    }
+    if !value.is_finite() {
+        bail!("the result is not a finite number");
+    }
+
+    Ok(value)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Identifier(String),
+    Operator(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "number `{}`", number),
+            Token::Identifier(identifier) => write!(f, "`{}`", identifier),
+            Token::Operator(operator) => write!(f, "`{}`", operator),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = expression.char_indices().peekable();
+
+    while let Some(&(start, char)) = chars.peek() {
+        if char.is_whitespace() {
+            chars.next();
+        } else if char.is_ascii_digit() || char == '.' {
+            let mut end = start;
+            while let Some(&(index, char)) = chars.peek() {
+                if !char.is_ascii_digit() && char != '.' {
+                    break;
+                }
+                end = index + char.len_utf8();
+                chars.next();
+            }
+            let number = &expression[start..end];
+            tokens.push(Token::Number(
+                number
+                    .parse()
+                    .map_err(|_| anyhow!("invalid number `{}`", number))?,
+            ));
+        } else if char.is_alphabetic() {
+            let mut end = start;
+            while let Some(&(index, char)) = chars.peek() {
+                if !char.is_alphanumeric() {
+                    break;
+                }
+                end = index + char.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Identifier(expression[start..end].to_lowercase()));
+        } else if "+-*/%^(),".contains(char) {
+            tokens.push(Token::Operator(char));
+            chars.next();
+        } else {
+            bail!("unexpected character `{}` at position {}", char, start);
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// The deepest nesting of parentheses, function calls and unary operators, past which the
+/// parser would risk overflowing the stack.
+const MAX_DEPTH: usize = 256;
+
+/// A recursive descent parser, evaluating the expression while parsing it.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// The number of nested `unary` calls, which every recursion goes through.
+    depth: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, operator: char) -> bool {
+        if self.tokens.get(self.position) == Some(&Token::Operator(operator)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, operator: char) -> Result<()> {
+        match self.next() {
+            Some(Token::Operator(found)) if found == operator => Ok(()),
+            Some(token) => bail!("expected `{}`, found {}", operator, token),
+            None => bail!("expected `{}`, found the end of the expression", operator),
+        }
+    }
+
+    /// expression = term (("+" | "-") term)*
+    fn expression(&mut self) -> Result<f64> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// term = unary (("*" | "/" | "%") unary)*
+    fn term(&mut self) -> Result<f64> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    bail!("division by zero");
+                }
+                value /= divisor;
+            } else if self.eat('%') {
+                let divisor = self.unary()?;
+                if divisor == 0.0 {
+                    bail!("division by zero");
+                }
+                value %= divisor;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// unary = ("-" | "+") unary | power
+    fn unary(&mut self) -> Result<f64> {
+        if self.depth == MAX_DEPTH {
+            bail!("the expression is nested too deeply");
+        }
+
+        self.depth += 1;
+        let value = if self.eat('-') {
+            self.unary().map(|value| -value)
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.power()
+        };
+        self.depth -= 1;
+        value
+    }
+
+    /// power = primary ("^" unary)?, which is right associative.
+    fn power(&mut self) -> Result<f64> {
+        let base = self.primary()?;
+        if self.eat('^') {
+            Ok(base.powf(self.unary()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    /// primary = number | constant | function "(" arguments ")" | "(" expression ")"
+    fn primary(&mut self) -> Result<f64> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(number),
+            Some(Token::Operator('(')) => {
+                let value = self.expression()?;
+                self.expect(')')?;
+                Ok(value)
+            }
+            Some(Token::Identifier(identifier)) => match identifier.as_str() {
+                "pi" => Ok(std::f64::consts::PI),
+                "e" => Ok(std::f64::consts::E),
+                function => {
+                    self.expect('(')?;
+                    let mut arguments = vec![self.expression()?];
+                    while self.eat(',') {
+                        arguments.push(self.expression()?);
+                    }
+                    self.expect(')')?;
+                    call(function, &arguments)
+                }
+            },
+            Some(token) => bail!("unexpected {}", token),
+            None => bail!("unexpected end of the expression"),
+        }
+    }
+}
+
+fn call(function: &str, arguments: &[f64]) -> Result<f64> {
+    let unary = |f: fn(f64) -> f64| match arguments {
+        [argument] => Ok(f(*argument)),
+        _ => Err(anyhow!("`{}` takes exactly one argument", function)),
+    };
+
+    match function {
+        "abs" => unary(f64::abs),
+        "sqrt" => unary(f64::sqrt),
+        "exp" => unary(f64::exp),
+        "ln" => unary(f64::ln),
+        "log10" => unary(f64::log10),
+        "sin" => unary(f64::sin),
+        "cos" => unary(f64::cos),
+        "tan" => unary(f64::tan),
+        "floor" => unary(f64::floor),
+        "ceil" => unary(f64::ceil),
+        "round" => unary(f64::round),
+        "min" => Ok(arguments.iter().copied().fold(f64::INFINITY, f64::min)),
+        "max" => Ok(arguments.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+        _ => bail!("unknown function `{}`", function),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(evaluate("1 + 2 * 3").unwrap(), 7.0);
+        assert_eq!(evaluate("(1 + 2) * 3").unwrap(), 9.0);
+        assert_eq!(evaluate("2 ^ 3 ^ 2").unwrap(), 512.0);
+        assert_eq!(evaluate("-2 ^ 2").unwrap(), -4.0);
+        assert_eq!(evaluate("10 % 4 - -1").unwrap(), 3.0);
+        assert_eq!(evaluate("max(1, sqrt(16), 3) / 2").unwrap(), 2.0);
+        assert_eq!(evaluate("round(pi * 100)").unwrap(), 314.0);
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        for (expression, error) in [
+            ("1 / 0", "division by zero"),
+            ("1 +", "unexpected end of the expression"),
+            ("(1 + 2", "expected `)`, found the end of the expression"),
+            ("2 $ 3", "unexpected character `$` at position 2"),
+            ("foo(1)", "unknown function `foo`"),
+            ("1 2", "unexpected number `2`"),
+            ("sqrt(-1)", "the result is not a finite number"),
+        ] {
+            assert_eq!(evaluate(expression).unwrap_err().to_string(), error);
+        }
+
+        for expression in [
+            "(".repeat(100_000) + "1" + &")".repeat(100_000),
+            "-".repeat(100_000) + "1",
+        ] {
+            assert_eq!(
+                evaluate(&expression).unwrap_err().to_string(),
+                "the expression is nested too deeply"
+            );
+        }
+        let nested = "(".repeat(200) + "1" + &")".repeat(200);
+        assert_eq!(evaluate(&nested).unwrap(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_calculator_tool() {
+        let output = CalculatorTool
+            .execute(json!({ "expression": "1.5 * 4" }))
+            .await
+            .unwrap();
+        assert_eq!(output, "6.0");
+    }
+}
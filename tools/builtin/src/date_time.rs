@@ -0,0 +1,254 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, TimeDelta, Utc};
+use ferrochain::tool::{execute_typed, Tool};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Tells the current date and time, and performs calendar arithmetic.
+#[derive(Clone)]
+pub struct DateTimeTool {
+    clock: Arc<dyn Fn() -> DateTime<Utc> + Send + Sync>,
+}
+
+impl DateTimeTool {
+    pub fn new() -> Self {
+        Self {
+            clock: Arc::new(Utc::now),
+        }
+    }
+
+    /// Replaces the system clock, for example to replay a conversation.
+    pub fn with_clock<F>(mut self, clock: F) -> Self
+    where
+        F: Fn() -> DateTime<Utc> + Send + Sync + 'static,
+    {
+        self.clock = Arc::new(clock);
+        self
+    }
+}
+
+impl Default for DateTimeTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The operations of [`DateTimeTool`].
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DateTimeOperation {
+    Now,
+    Add,
+    Difference,
+    Format,
+}
+
+/// The input of [`DateTimeTool`], a single object whose fields are used depending on its
+/// `operation`.
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DateTimeInput {
+    /// `now` returns the current date and time, `add` adds a duration, which may be negative,
+    /// to `datetime`, `difference` returns the number of seconds from `from` to `to`, and
+    /// `format` formats `datetime`.
+    pub operation: DateTimeOperation,
+    /// For `now`, the UTC offset of the returned date and time, such as `+02:00`. Defaults to
+    /// UTC.
+    pub utc_offset: Option<String>,
+    /// For `add` and `format`, an RFC 3339 date and time, such as
+    /// `2024-05-01T10:00:00+02:00`.
+    pub datetime: Option<DateTime<FixedOffset>>,
+    /// For `add`, the weeks to add.
+    #[serde(default)]
+    pub weeks: i64,
+    /// For `add`, the days to add.
+    #[serde(default)]
+    pub days: i64,
+    /// For `add`, the hours to add.
+    #[serde(default)]
+    pub hours: i64,
+    /// For `add`, the minutes to add.
+    #[serde(default)]
+    pub minutes: i64,
+    /// For `add`, the seconds to add.
+    #[serde(default)]
+    pub seconds: i64,
+    /// For `difference`, an RFC 3339 date and time.
+    pub from: Option<DateTime<FixedOffset>>,
+    /// For `difference`, an RFC 3339 date and time, the difference being negative if it's
+    /// before `from`.
+    pub to: Option<DateTime<FixedOffset>>,
+    /// For `format`, a strftime format string, such as `%A %e %B %Y`.
+    pub format: Option<String>,
+}
+
+/// Returns the `field` of the `operation`, which is required.
+fn required<T>(value: Option<T>, operation: &str, field: &str) -> Result<T> {
+    value.ok_or_else(|| anyhow!("`{}` needs a `{}`", operation, field))
+}
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum DateTimeOutput {
+    DateTime {
+        datetime: DateTime<FixedOffset>,
+        weekday: String,
+    },
+    Difference {
+        seconds: i64,
+    },
+    Formatted(String),
+}
+
+impl From<DateTime<FixedOffset>> for DateTimeOutput {
+    fn from(datetime: DateTime<FixedOffset>) -> Self {
+        DateTimeOutput::DateTime {
+            weekday: datetime.format("%A").to_string(),
+            datetime,
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for DateTimeTool {
+    type Input = DateTimeInput;
+    type Output = DateTimeOutput;
+
+    fn name(&self) -> String {
+        "date_time".into()
+    }
+
+    fn description(&self) -> String {
+        "Returns the current date and time, adds durations to dates, computes the time between two dates and formats dates.".into()
+    }
+
+    async fn execute(&self, input: Value) -> Result<String> {
+        execute_typed(input, |input: DateTimeInput| async move {
+            Ok(match input.operation {
+                DateTimeOperation::Now => {
+                    let offset = match input.utc_offset {
+                        Some(offset) => offset
+                            .parse::<FixedOffset>()
+                            .map_err(|_| anyhow!("invalid UTC offset `{}`", offset))?,
+                        None => FixedOffset::east_opt(0).expect("UTC is a valid offset"),
+                    };
+                    (self.clock)().with_timezone(&offset).into()
+                }
+                DateTimeOperation::Add => {
+                    let datetime = required(input.datetime, "add", "datetime")?;
+                    let delta = [
+                        TimeDelta::try_weeks(input.weeks),
+                        TimeDelta::try_days(input.days),
+                        TimeDelta::try_hours(input.hours),
+                        TimeDelta::try_minutes(input.minutes),
+                        TimeDelta::try_seconds(input.seconds),
+                    ]
+                    .into_iter()
+                    .try_fold(TimeDelta::zero(), |total, delta| total.checked_add(&delta?))
+                    .ok_or_else(|| anyhow!("the duration is out of range"))?;
+
+                    datetime
+                        .checked_add_signed(delta)
+                        .ok_or_else(|| anyhow!("the resulting date is out of range"))?
+                        .into()
+                }
+                DateTimeOperation::Difference => {
+                    let from = required(input.from, "difference", "from")?;
+                    let to = required(input.to, "difference", "to")?;
+                    DateTimeOutput::Difference {
+                        seconds: (to - from).num_seconds(),
+                    }
+                }
+                DateTimeOperation::Format => {
+                    let datetime = required(input.datetime, "format", "datetime")?;
+                    let format = required(input.format, "format", "format")?;
+                    let mut formatted = String::new();
+                    std::fmt::write(&mut formatted, format_args!("{}", datetime.format(&format)))
+                        .map_err(|_| anyhow!("invalid format `{}`", format))?;
+                    DateTimeOutput::Formatted(formatted)
+                }
+            })
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    async fn execute(input: Value) -> Result<DateTimeOutput> {
+        let tool = DateTimeTool::new().with_clock(|| {
+            DateTime::parse_from_rfc3339("2024-02-28T23:30:00Z")
+                .unwrap()
+                .to_utc()
+        });
+        let output = tool.execute(input).await?;
+        Ok(serde_json::from_str(&output).unwrap_or(DateTimeOutput::Formatted(output)))
+    }
+
+    fn datetime(datetime: &str, weekday: &str) -> DateTimeOutput {
+        DateTimeOutput::DateTime {
+            datetime: DateTime::parse_from_rfc3339(datetime).unwrap(),
+            weekday: weekday.into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_date_time_tool() {
+        assert_eq!(
+            execute(json!({ "operation": "now", "utc_offset": "+02:00" }))
+                .await
+                .unwrap(),
+            datetime("2024-02-29T01:30:00+02:00", "Thursday")
+        );
+        assert_eq!(
+            execute(json!({ "operation": "add", "datetime": "2024-02-28T12:00:00Z", "days": 2, "hours": -1 }))
+                .await
+                .unwrap(),
+            datetime("2024-03-01T11:00:00Z", "Friday")
+        );
+        assert_eq!(
+            execute(json!({ "operation": "difference", "from": "2024-01-01T00:00:00Z", "to": "2024-01-01T01:00:00+01:00" }))
+                .await
+                .unwrap(),
+            DateTimeOutput::Difference { seconds: 0 }
+        );
+        assert_eq!(
+            execute(json!({ "operation": "format", "datetime": "2024-02-29T10:00:00Z", "format": "%A %e %B %Y" }))
+                .await
+                .unwrap(),
+            DateTimeOutput::Formatted("Thursday 29 February 2024".into())
+        );
+
+        assert!(execute(json!({ "operation": "now", "utc_offset": "Mars" }))
+            .await
+            .is_err());
+        assert!(execute(
+            json!({ "operation": "format", "datetime": "2024-02-29T10:00:00Z", "format": "%Q" })
+        )
+        .await
+        .is_err());
+        assert!(
+            execute(json!({ "operation": "difference", "from": "2024-01-01T00:00:00Z" }))
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_input_schema() {
+        // Providers such as Anthropic and MCP need the input to be a plain object.
+        let schema = serde_json::to_value(schemars::schema_for!(DateTimeInput)).unwrap();
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["required"], json!(["operation"]));
+        assert!(schema["properties"]["datetime"].is_object());
+        assert!(schema.get("oneOf").is_none());
+    }
+}
@@ -0,0 +1,489 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use ferrochain::tool::{execute_typed, Tool};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const DEFAULT_MAX_BYTES: u64 = 1024 * 1024;
+const DEFAULT_MAX_RESULTS: usize = 50;
+
+/// A directory outside of which no file can be accessed.
+#[derive(Clone, Debug)]
+struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    fn new<P>(root: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let root = root.as_ref();
+        Ok(Self {
+            root: root
+                .canonicalize()
+                .with_context(|| format!("cannot open sandbox root {}", root.display()))?,
+        })
+    }
+
+    /// Resolves `path`, relative to the root, into an existing path inside the sandbox.
+    ///
+    /// Symbolic links are followed before checking the path, so that they cannot be used to
+    /// escape the sandbox.
+    fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let relative = Path::new(path);
+        if relative.is_absolute() {
+            bail!("`{}` must be relative to the sandbox root", path);
+        }
+        if relative
+            .components()
+            .any(|component| matches!(component, Component::ParentDir))
+        {
+            bail!("`{}` must not contain `..`", path);
+        }
+
+        let resolved = self
+            .root
+            .join(relative)
+            .canonicalize()
+            .map_err(|_| anyhow!("`{}` does not exist", path))?;
+        if !resolved.starts_with(&self.root) {
+            bail!("`{}` is outside of the sandbox", path);
+        }
+
+        Ok(resolved)
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+fn current_directory() -> String {
+    ".".into()
+}
+
+/// Reads text files inside a root directory.
+pub struct ReadFileTool {
+    sandbox: Sandbox,
+    max_bytes: u64,
+}
+
+impl ReadFileTool {
+    pub fn new<P>(root: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self {
+            sandbox: Sandbox::new(root)?,
+            max_bytes: DEFAULT_MAX_BYTES,
+        })
+    }
+
+    /// Sets the size of the largest file which can be read, 1 MiB by default.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ReadFileInput {
+    /// Path of the file, relative to the root directory.
+    path: String,
+}
+
+#[async_trait]
+impl Tool for ReadFileTool {
+    type Input = ReadFileInput;
+    type Output = String;
+
+    fn name(&self) -> String {
+        "read_file".into()
+    }
+
+    fn description(&self) -> String {
+        "Reads the content of a UTF-8 text file.".into()
+    }
+
+    async fn execute(&self, input: Value) -> Result<String> {
+        execute_typed(input, |input: ReadFileInput| async move {
+            let path = self.sandbox.resolve(&input.path)?;
+            let metadata = tokio::fs::metadata(&path).await?;
+            if !metadata.is_file() {
+                bail!("`{}` is not a file", input.path);
+            }
+            if metadata.len() > self.max_bytes {
+                bail!(
+                    "`{}` is {} bytes long, more than the limit of {} bytes",
+                    input.path,
+                    metadata.len(),
+                    self.max_bytes
+                );
+            }
+
+            String::from_utf8(tokio::fs::read(&path).await?)
+                .map_err(|_| anyhow!("`{}` is not a UTF-8 text file", input.path))
+        })
+        .await
+    }
+}
+
+/// Lists the content of directories inside a root directory.
+pub struct ListDirectoryTool {
+    sandbox: Sandbox,
+}
+
+impl ListDirectoryTool {
+    pub fn new<P>(root: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self {
+            sandbox: Sandbox::new(root)?,
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ListDirectoryInput {
+    /// Path of the directory, relative to the root directory. Defaults to the root directory.
+    #[serde(default = "current_directory")]
+    path: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink,
+}
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct DirectoryEntry {
+    /// Path of the entry, relative to the root directory.
+    pub path: String,
+    pub kind: EntryKind,
+    /// Size of the entry, in bytes.
+    pub size: u64,
+}
+
+#[async_trait]
+impl Tool for ListDirectoryTool {
+    type Input = ListDirectoryInput;
+    type Output = Vec<DirectoryEntry>;
+
+    fn name(&self) -> String {
+        "list_directory".into()
+    }
+
+    fn description(&self) -> String {
+        "Lists the files and directories contained in a directory, sorted by path.".into()
+    }
+
+    async fn execute(&self, input: Value) -> Result<String> {
+        execute_typed(input, |input: ListDirectoryInput| async move {
+            let path = self.sandbox.resolve(&input.path)?;
+            let mut entries = vec![];
+            let mut directory = tokio::fs::read_dir(&path)
+                .await
+                .with_context(|| format!("`{}` is not a directory", input.path))?;
+            while let Some(entry) = directory.next_entry().await? {
+                let metadata = entry.metadata().await?;
+                entries.push(DirectoryEntry {
+                    path: self.sandbox.relative(&entry.path()),
+                    kind: if metadata.is_symlink() {
+                        EntryKind::Symlink
+                    } else if metadata.is_dir() {
+                        EntryKind::Directory
+                    } else {
+                        EntryKind::File
+                    },
+                    size: metadata.len(),
+                });
+            }
+            entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+            Ok(entries)
+        })
+        .await
+    }
+}
+
+/// Searches for text in the files inside a root directory.
+pub struct SearchFilesTool {
+    sandbox: Sandbox,
+    max_bytes: u64,
+}
+
+impl SearchFilesTool {
+    pub fn new<P>(root: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self {
+            sandbox: Sandbox::new(root)?,
+            max_bytes: DEFAULT_MAX_BYTES,
+        })
+    }
+
+    /// Sets the size of the largest file which is searched, 1 MiB by default. Larger files are
+    /// skipped.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SearchFilesInput {
+    /// Text to search for, matched case-insensitively against each line.
+    #[schemars(length(min = 1))]
+    query: String,
+    /// Path of the directory to search in, relative to the root directory. Defaults to the
+    /// root directory.
+    #[serde(default = "current_directory")]
+    path: String,
+    /// Maximum number of matching lines to return.
+    #[serde(default = "default_max_results")]
+    #[schemars(range(min = 1))]
+    max_results: usize,
+}
+
+fn default_max_results() -> usize {
+    DEFAULT_MAX_RESULTS
+}
+
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+pub struct SearchMatch {
+    /// Path of the file, relative to the root directory.
+    pub path: String,
+    /// Line number of the match, starting at 1.
+    pub line: usize,
+    pub text: String,
+}
+
+#[async_trait]
+impl Tool for SearchFilesTool {
+    type Input = SearchFilesInput;
+    type Output = Vec<SearchMatch>;
+
+    fn name(&self) -> String {
+        "search_files".into()
+    }
+
+    fn description(&self) -> String {
+        "Searches recursively for the lines of text files containing a piece of text.".into()
+    }
+
+    async fn execute(&self, input: Value) -> Result<String> {
+        execute_typed(input, |input: SearchFilesInput| async move {
+            let query = input.query.to_lowercase();
+            let mut matches = vec![];
+            let root = self.sandbox.resolve(&input.path)?;
+            let mut directories = vec![root.clone()];
+
+            // Symbolic links are not followed, so that the search stays inside the sandbox.
+            while let Some(directory) = directories.pop() {
+                let entries = match read_entries(&directory).await {
+                    Ok(entries) => entries,
+                    // Unreadable directories are skipped, like any file which can't be read.
+                    Err(_) if directory != root => continue,
+                    Err(error) => return Err(error.into()),
+                };
+
+                for entry in entries {
+                    let Ok(file_type) = entry.file_type().await else {
+                        continue;
+                    };
+                    if file_type.is_dir() {
+                        directories.push(entry.path());
+                        continue;
+                    }
+                    if !file_type.is_file() {
+                        continue;
+                    }
+                    match entry.metadata().await {
+                        Ok(metadata) if metadata.len() <= self.max_bytes => {}
+                        _ => continue,
+                    }
+
+                    let Ok(Ok(content)) =
+                        tokio::fs::read(entry.path()).await.map(String::from_utf8)
+                    else {
+                        continue;
+                    };
+                    for (index, line) in content.lines().enumerate() {
+                        if line.to_lowercase().contains(&query) {
+                            matches.push(SearchMatch {
+                                path: self.sandbox.relative(&entry.path()),
+                                line: index + 1,
+                                text: line.trim().into(),
+                            });
+                            if matches.len() >= input.max_results {
+                                return Ok(matches);
+                            }
+                        }
+                    }
+                }
+            }
+
+            Ok(matches)
+        })
+        .await
+    }
+}
+
+/// Reads the entries of `directory`, sorted by path.
+async fn read_entries(directory: &Path) -> std::io::Result<Vec<tokio::fs::DirEntry>> {
+    let mut entries = vec![];
+    let mut reader = tokio::fs::read_dir(directory).await?;
+    while let Some(entry) = reader.next_entry().await? {
+        entries.push(entry);
+    }
+    entries.sort_by_key(|entry| entry.path());
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn temp_dir() -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("docs")).unwrap();
+        std::fs::write(
+            dir.path().join("README.md"),
+            "# Ferrochain\nHello, world!\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("docs/guide.md"), "Say hello to tools.\n").unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_read_and_list_files() {
+        let dir = temp_dir();
+
+        let tool = ReadFileTool::new(&dir.path()).unwrap();
+        let content = tool.execute(json!({ "path": "README.md" })).await.unwrap();
+        assert_eq!(content, "# Ferrochain\nHello, world!\n");
+
+        let tool = ListDirectoryTool::new(&dir.path()).unwrap();
+        let entries: Vec<DirectoryEntry> =
+            serde_json::from_str(&tool.execute(json!({})).await.unwrap()).unwrap();
+        let entries = entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), &entry.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                ("README.md", &EntryKind::File),
+                ("docs", &EntryKind::Directory)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_paths_cannot_escape_the_sandbox() {
+        let dir = temp_dir();
+        let tool = ReadFileTool::new(dir.path().join("docs")).unwrap();
+
+        for path in ["../README.md", "/etc/passwd", "missing.md"] {
+            assert!(tool.execute(json!({ "path": path })).await.is_err());
+        }
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(
+                dir.path().join("README.md"),
+                dir.path().join("docs/link.md"),
+            )
+            .unwrap();
+            let error = tool
+                .execute(json!({ "path": "link.md" }))
+                .await
+                .unwrap_err();
+            assert_eq!(error.to_string(), "`link.md` is outside of the sandbox");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_files() {
+        let dir = temp_dir();
+        let tool = SearchFilesTool::new(&dir.path()).unwrap();
+
+        let matches: Vec<SearchMatch> =
+            serde_json::from_str(&tool.execute(json!({ "query": "HELLO" })).await.unwrap())
+                .unwrap();
+        assert_eq!(
+            matches,
+            [
+                SearchMatch {
+                    path: "README.md".into(),
+                    line: 2,
+                    text: "Hello, world!".into(),
+                },
+                SearchMatch {
+                    path: "docs/guide.md".into(),
+                    line: 1,
+                    text: "Say hello to tools.".into(),
+                },
+            ]
+        );
+
+        let matches: Vec<SearchMatch> = serde_json::from_str(
+            &tool
+                .execute(json!({ "query": "hello", "max_results": 1 }))
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(matches.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_search_skips_unreadable_entries() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir();
+        std::fs::create_dir(dir.path().join("private")).unwrap();
+        std::fs::write(dir.path().join("private/notes.md"), "hello\n").unwrap();
+        std::fs::write(dir.path().join("secret.md"), "hello\n").unwrap();
+        for path in ["private", "secret.md"] {
+            std::fs::set_permissions(dir.path().join(path), PermissionsExt::from_mode(0o000))
+                .unwrap();
+        }
+
+        // Unless the tests run as root, the entries can't be read and are skipped.
+        let tool = SearchFilesTool::new(dir.path()).unwrap();
+        let matches: Vec<SearchMatch> =
+            serde_json::from_str(&tool.execute(json!({ "query": "hello" })).await.unwrap())
+                .unwrap();
+        let paths = matches
+            .iter()
+            .map(|found| found.path.as_str())
+            .collect::<Vec<_>>();
+        assert!(paths.contains(&"README.md"));
+        assert!(paths.contains(&"docs/guide.md"));
+
+        // The permissions are restored so that the directory can be removed.
+        std::fs::set_permissions(dir.path().join("private"), PermissionsExt::from_mode(0o755))
+            .unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use ferrochain::tool::{execute_typed, Tool};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Extracts values out of JSON data with path expressions.
+#[derive(Clone, Debug, Default)]
+pub struct JsonQueryTool;
+
+impl JsonQueryTool {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct JsonQueryInput {
+    /// The JSON data to query.
+    data: Value,
+    /// The path of the values to extract, such as `.users[0].name` or `$.users[*].name`.
+    ///
+    /// A path starts with an optional `$`, followed by any number of segments: `.key` or
+    /// `["key"]` select a key of an object, `[index]` selects an element of an array,
+    /// negative indices counting from the end, `[]` or `[*]` select every element of an
+    /// array or object, and `..key` selects the key at any depth.
+    query: String,
+}
+
+#[async_trait]
+impl Tool for JsonQueryTool {
+    type Input = JsonQueryInput;
+    type Output = Vec<Value>;
+
+    fn name(&self) -> String {
+        "json_query".into()
+    }
+
+    fn description(&self) -> String {
+        "Extracts values out of JSON data with a JSONPath or jq-like path, returning the list of matching values.".into()
+    }
+
+    async fn execute(&self, input: Value) -> Result<String> {
+        execute_typed(input, |input: JsonQueryInput| async move {
+            query(&input.data, &input.query)
+        })
+        .await
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(i64),
+    Wildcard,
+    Descendant(String),
+}
+
+/// Returns the values of `data` matching the path `query`.
+pub fn query(data: &Value, query: &str) -> Result<Vec<Value>> {
+    let mut values = vec![data];
+
+    for segment in parse(query)? {
+        values = values
+            .into_iter()
+            .flat_map(|value| select(value, &segment))
+            .collect();
+    }
+
+    Ok(values.into_iter().cloned().collect())
+}
+
+fn select<'a>(value: &'a Value, segment: &Segment) -> Vec<&'a Value> {
+    match (segment, value) {
+        (Segment::Key(key), Value::Object(object)) => object.get(key).into_iter().collect(),
+        (Segment::Index(index), Value::Array(array)) => {
+            let index = if *index < 0 {
+                array.len() as i64 + index
+            } else {
+                *index
+            };
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| array.get(index))
+                .into_iter()
+                .collect()
+        }
+        (Segment::Wildcard, Value::Array(array)) => array.iter().collect(),
+        (Segment::Wildcard, Value::Object(object)) => object.values().collect(),
+        (Segment::Descendant(key), _) => {
+            let mut values = vec![];
+            descendants(value, key, &mut values);
+            values
+        }
+        _ => vec![],
+    }
+}
+
+fn descendants<'a>(value: &'a Value, key: &str, values: &mut Vec<&'a Value>) {
+    match value {
+        Value::Object(object) => {
+            if let Some(value) = object.get(key) {
+                values.push(value);
+            }
+            for value in object.values() {
+                descendants(value, key, values);
+            }
+        }
+        Value::Array(array) => {
+            for value in array {
+                descendants(value, key, values);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn parse(query: &str) -> Result<Vec<Segment>> {
+    let query = query.trim();
+    let mut rest = query.strip_prefix('$').unwrap_or(query);
+    let mut segments = vec![];
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("..") {
+            let (key, after) = identifier(after);
+            if key.is_empty() {
+                bail!("expected a key after `..` in `{}`", query);
+            }
+            segments.push(Segment::Descendant(key.into()));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = if let Some(quoted) = after.strip_prefix('"') {
+                quoted
+                    .find('"')
+                    .map(|end| end + 2)
+                    .filter(|end| after[*end..].starts_with(']'))
+            } else {
+                after.find(']')
+            }
+            .ok_or_else(|| anyhow!("unclosed `[` in `{}`", query))?;
+
+            let inner = after[..end].trim();
+            segments.push(match inner {
+                "" | "*" => Segment::Wildcard,
+                _ if inner.starts_with('"') => Segment::Key(
+                    serde_json::from_str(inner)
+                        .map_err(|_| anyhow!("invalid key `{}` in `{}`", inner, query))?,
+                ),
+                _ => Segment::Index(
+                    inner
+                        .parse()
+                        .map_err(|_| anyhow!("invalid index `{}` in `{}`", inner, query))?,
+                ),
+            });
+            rest = &after[end + 1..];
+        } else if let Some(after) = rest.strip_prefix('.') {
+            if after.is_empty() || after.starts_with('[') {
+                rest = after;
+                continue;
+            }
+            if let Some(after) = after.strip_prefix('*') {
+                segments.push(Segment::Wildcard);
+                rest = after;
+                continue;
+            }
+            let (key, after) = identifier(after);
+            if key.is_empty() {
+                bail!("expected a key after `.` in `{}`", query);
+            }
+            segments.push(Segment::Key(key.into()));
+            rest = after;
+        } else {
+            bail!("unexpected `{}` in `{}`", rest, query);
+        }
+    }
+
+    Ok(segments)
+}
+
+fn identifier(source: &str) -> (&str, &str) {
+    let end = source
+        .find(|char: char| !char.is_alphanumeric() && char != '_' && char != '-')
+        .unwrap_or(source.len());
+    source.split_at(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_query() {
+        let data = json!({
+            "users": [
+                { "name": "Ada", "address": { "city": "London" } },
+                { "name": "Grace", "address": { "city": "New York" } },
+            ],
+            "a key": 1,
+        });
+
+        assert_eq!(query(&data, ".users[0].name").unwrap(), [json!("Ada")]);
+        assert_eq!(query(&data, "$.users[-1].name").unwrap(), [json!("Grace")]);
+        assert_eq!(
+            query(&data, ".users[].name").unwrap(),
+            [json!("Ada"), json!("Grace")]
+        );
+        assert_eq!(
+            query(&data, "$..city").unwrap(),
+            [json!("London"), json!("New York")]
+        );
+        assert_eq!(query(&data, r#".["a key"]"#).unwrap(), [json!(1)]);
+        assert_eq!(query(&data, ".").unwrap(), vec![data.clone()]);
+        assert!(query(&data, ".missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_queries() {
+        let data = json!({});
+        assert!(query(&data, ".users[0").is_err());
+        assert!(query(&data, ".users[x]").is_err());
+        assert!(query(&data, "users").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_json_query_tool() {
+        let output = JsonQueryTool
+            .execute(json!({ "data": { "a": [1, 2] }, "query": ".a[*]" }))
+            .await
+            .unwrap();
+        assert_eq!(output, "[1,2]");
+    }
+}
@@ -0,0 +1,9 @@
+mod calculator;
+mod date_time;
+mod fs;
+mod json_query;
+
+pub use calculator::CalculatorTool;
+pub use date_time::DateTimeTool;
+pub use fs::{ListDirectoryTool, ReadFileTool, SearchFilesTool};
+pub use json_query::JsonQueryTool;
@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use ferrochain::{
     anyhow::{anyhow, Result},
+    config::{ComponentFactory, Components},
     document::{Document, StoredDocument},
     embedding::Embedder,
     vector_store::{Similarity, VectorStore},
@@ -14,7 +15,7 @@ use qdrant_client::{
     },
     Payload, Qdrant,
 };
-use serde_json::json;
+use serde_json::{json, Value};
 use uuid::Uuid;
 
 pub struct QdrantVectorStore {
@@ -63,17 +64,18 @@ impl VectorStore for QdrantVectorStore {
         Ok(())
     }
 
-    async fn add_documents(&self, documents: &[Document]) -> Result<()> {
+    async fn add_documents(&self, documents: &[Document]) -> Result<Vec<String>> {
         let vectors = self
             .document_embedder
             .embed(documents.iter().map(|d| d.content.clone()).collect())
             .await?;
 
+        let ids = documents.iter().map(|_| Uuid::new_v4()).collect::<Vec<_>>();
         let points = documents
             .into_iter()
             .zip(vectors)
-            .map(|(Document { content, metadata }, vector)| {
-                let id = Uuid::new_v4();
+            .zip(&ids)
+            .map(|((Document { content, metadata }, vector), id)| {
                 PointStruct::new(
                     id.to_string(),
                     vector.to_vec(),
@@ -91,7 +93,7 @@ impl VectorStore for QdrantVectorStore {
             .upsert_points(UpsertPointsBuilder::new(&self.collection_name, points).wait(true))
             .await?;
 
-        Ok(())
+        Ok(ids.iter().map(Uuid::to_string).collect())
     }
 
     async fn delete_documents(&self, ids: &[String]) -> Result<()> {
@@ -224,3 +226,49 @@ impl QdrantVectorStoreBuilder {
         })
     }
 }
+
+/// Builds [`QdrantVectorStore`]s out of their configuration, see [`ferrochain::config`].
+///
+/// Embedders are referenced by name, either as a single `embedder` or as separate
+/// `query_embedder` and `document_embedder`.
+#[derive(Clone, Default)]
+pub struct QdrantVectorStoreFactory;
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QdrantVectorStoreParams {
+    url: String,
+    api_key: Option<String>,
+    collection_name: String,
+    embedder: Option<String>,
+    query_embedder: Option<String>,
+    document_embedder: Option<String>,
+    vector_size: u64,
+}
+
+#[ferrochain::async_trait]
+impl ComponentFactory<dyn VectorStore> for QdrantVectorStoreFactory {
+    async fn build(&self, params: Value, components: &Components) -> Result<Box<dyn VectorStore>> {
+        let params: QdrantVectorStoreParams = serde_json::from_value(params)?;
+
+        let client = Qdrant::from_url(&params.url)
+            .api_key(params.api_key)
+            .build()?;
+
+        let mut builder = QdrantVectorStore::builder()
+            .with_client(Arc::new(client))
+            .with_collection_name(params.collection_name)
+            .with_vector_size(params.vector_size);
+        if let Some(embedder) = params.embedder {
+            builder = builder.with_embedder(components.embedder(&embedder)?);
+        }
+        if let Some(embedder) = params.query_embedder {
+            builder = builder.with_query_embedder(components.embedder(&embedder)?);
+        }
+        if let Some(embedder) = params.document_embedder {
+            builder = builder.with_document_embedder(components.embedder(&embedder)?);
+        }
+
+        Ok(Box::new(builder.build()?))
+    }
+}
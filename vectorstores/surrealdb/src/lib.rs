@@ -1,12 +1,14 @@
 use std::sync::Arc;
 
 use ferrochain::{
-    anyhow::{anyhow, Result},
+    anyhow::{anyhow, bail, Result},
+    config::{ComponentFactory, Components},
     document::{Document, StoredDocument},
     embedding::Embedder,
     vector_store::{Similarity, VectorStore},
 };
-use surrealdb::{engine::any::Any, RecordId, Surreal};
+use serde_json::Value;
+use surrealdb::{engine::any::Any, opt::auth::Root, RecordId, Surreal};
 use uuid::Uuid;
 
 pub struct SurrealVectorStore {
@@ -59,12 +61,13 @@ impl VectorStore for SurrealVectorStore {
         Ok(())
     }
 
-    async fn add_documents(&self, documents: &[Document]) -> Result<()> {
+    async fn add_documents(&self, documents: &[Document]) -> Result<Vec<String>> {
         let vectors = self
             .document_embedder
             .embed(documents.iter().map(|d| d.content.clone()).collect())
             .await?;
 
+        let mut ids = Vec::with_capacity(documents.len());
         for (doc, vector) in documents.iter().zip(vectors) {
             let record_id =
                 RecordId::from((self.collection_name.clone(), Uuid::new_v4().to_string()));
@@ -81,9 +84,10 @@ impl VectorStore for SurrealVectorStore {
                 .bind(("metadata", stored_doc.document.metadata.clone()))
                 .await?;
             resp.take::<Vec<()>>(0)?;
+            ids.push(stored_doc.id);
         }
 
-        Ok(())
+        Ok(ids)
     }
 
     async fn delete_documents(&self, ids: &[String]) -> Result<()> {
@@ -192,3 +196,61 @@ impl SurrealVectorStoreBuilder {
         })
     }
 }
+
+/// Builds [`SurrealVectorStore`]s out of their configuration, see [`ferrochain::config`].
+///
+/// Embedders are referenced by name, either as a single `embedder` or as separate
+/// `query_embedder` and `document_embedder`.
+#[derive(Clone, Default)]
+pub struct SurrealVectorStoreFactory;
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SurrealVectorStoreParams {
+    url: String,
+    namespace: String,
+    database: String,
+    username: Option<String>,
+    password: Option<String>,
+    collection_name: String,
+    embedder: Option<String>,
+    query_embedder: Option<String>,
+    document_embedder: Option<String>,
+    vector_size: u64,
+}
+
+#[ferrochain::async_trait]
+impl ComponentFactory<dyn VectorStore> for SurrealVectorStoreFactory {
+    async fn build(&self, params: Value, components: &Components) -> Result<Box<dyn VectorStore>> {
+        let params: SurrealVectorStoreParams = serde_json::from_value(params)?;
+
+        if params.username.is_some() != params.password.is_some() {
+            bail!("username and password must be given together");
+        }
+
+        let client = surrealdb::engine::any::connect(params.url).await?;
+        if let (Some(username), Some(password)) = (&params.username, &params.password) {
+            client.signin(Root { username, password }).await?;
+        }
+        client
+            .use_ns(params.namespace)
+            .use_db(params.database)
+            .await?;
+
+        let mut builder = SurrealVectorStore::builder()
+            .with_client(Arc::new(client))
+            .with_collection_name(params.collection_name)
+            .with_vector_size(params.vector_size);
+        if let Some(embedder) = params.embedder {
+            builder = builder.with_embedder(components.embedder(&embedder)?);
+        }
+        if let Some(embedder) = params.query_embedder {
+            builder = builder.with_query_embedder(components.embedder(&embedder)?);
+        }
+        if let Some(embedder) = params.document_embedder {
+            builder = builder.with_document_embedder(components.embedder(&embedder)?);
+        }
+
+        Ok(Box::new(builder.build()?))
+    }
+}